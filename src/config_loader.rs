@@ -4,6 +4,7 @@ use std::path::Path;
 use std::fs::File;
 use std::error::Error;
 use std::io::Read;
+use game::FixedTimestep;
 
 struct ConfigSettings<'a> {
   title: &'a str,
@@ -16,7 +17,10 @@ struct ConfigSettings<'a> {
   srgb: bool,
   resizable: bool,
   decorated: bool,
-  controllers: bool
+  controllers: bool,
+  // fixed updates per second; 0 leaves them to piston's update events
+  fixed_update_rate: f64,
+  max_fixed_updates: u32
 }
 
 impl<'a> ConfigSettings<'a> {
@@ -32,7 +36,9 @@ impl<'a> ConfigSettings<'a> {
     srgb: bool,
     resizable: bool,
     decorated: bool,
-    controllers: bool
+    controllers: bool,
+    fixed_update_rate: f64,
+    max_fixed_updates: u32
   ) -> ConfigSettings {
     ConfigSettings {
       title: title,
@@ -45,39 +51,52 @@ impl<'a> ConfigSettings<'a> {
       srgb: srgb,
       resizable: resizable,
       decorated: decorated,
-      controllers: controllers
+      controllers: controllers,
+      fixed_update_rate: fixed_update_rate,
+      max_fixed_updates: max_fixed_updates
     }
   }
 }
 
+// also used to load prefab files
+pub fn read_yaml_docs(path: &str) -> Result<Vec<Yaml>, String> {
+  let path = Path::new(path);
+  let display = path.display();
+  let mut file = match File::open(&path) {
+    Err(why) => return Err(format!("Couldn't open {}: {}", display,
+                                   why.description())),
+    Ok(file) => file,
+  };
+  let mut file_str = String::new();
+  match file.read_to_string(&mut file_str) {
+    Err(why) => return Err(format!("Couldn't read {}: {}", display,
+                                   why.description())),
+    Ok(_) => (),
+  };
+  match YamlLoader::load_from_str(&file_str) {
+    Err(why) => Err(format!("Couldn't load YAML docs from {}: {}", display,
+                            why.description())),
+    Ok(docs) => Ok(docs),
+  }
+}
+
 pub struct ConfigLoader;
 
 impl ConfigLoader {
-  pub fn load_config(&self, config_path: &str) -> PistonWindow {
-    let path = Path::new(config_path);
-    let display = path.display();
-    let mut file = match File::open(&path) {
-      Err(why) => panic!("Error: Couldn't open {}: {}", display,
-                         why.description()),
-      Ok(file) => file,
-    };
-    let mut file_str = String::new();
-    match file.read_to_string(&mut file_str) {
-      Err(why) => panic!("Error: Couldn't read {}: {}", display,
-                         why.description()),
-      Ok(_) => (),
-    };
-    let docs = match YamlLoader::load_from_str(&file_str) {
-      Err(why) => panic!("Error: Couldn't load YAML docs from {}: {}", display,
-                         why.description()),
-      Ok(docs) => docs,
-    };
+  pub fn load_config(&self, config_path: &str) -> (PistonWindow, Option<FixedTimestep>) {
+    let docs = read_yaml_docs(config_path).unwrap_or_else(|why| panic!("Error: {}", why));
     let doc: &Yaml = &docs[0];
 
     let settings = self.read_config(doc);
 
+    let fixed_timestep = if settings.fixed_update_rate > 0.0 {
+      Some(FixedTimestep::new(settings.fixed_update_rate, settings.max_fixed_updates))
+    } else {
+      None
+    };
+
     let opengl = OpenGL::V3_2;
-    WindowSettings::new(settings.title, [settings.width, settings.height])
+    let window = WindowSettings::new(settings.title, [settings.width, settings.height])
       .samples(settings.samples)
       .fullscreen(settings.fullscreen)
       .exit_on_esc(settings.exit_on_esc)
@@ -88,7 +107,8 @@ impl ConfigLoader {
       .controllers(settings.controllers)
       .opengl(opengl)
       .build()
-      .unwrap_or_else(|e| { panic!("Error: Failed to build PistonWindow: {}", e) })
+      .unwrap_or_else(|e| { panic!("Error: Failed to build PistonWindow: {}", e) });
+    (window, fixed_timestep)
   }
 
   fn read_config<'a>(&'a self, doc: &'a Yaml) -> ConfigSettings {
@@ -103,8 +123,19 @@ impl ConfigLoader {
     let resizable = doc["resizable"][0].as_bool().unwrap_or(true);
     let decorated = doc["decorated"][0].as_bool().unwrap_or(true);
     let controllers = doc["controllers"][0].as_bool().unwrap_or(true);
+    let fixed_update_rate = doc["fixed_update_rate"][0].as_f64()
+      .or(doc["fixed_update_rate"][0].as_i64().map(|rate| rate as f64))
+      .unwrap_or(0.0);
+    if !fixed_update_rate.is_finite() || fixed_update_rate < 0.0 {
+      panic!("Error: fixed_update_rate must be a finite number of at least 0, got {}", fixed_update_rate)
+    }
+    let max_fixed_updates = doc["max_fixed_updates"][0].as_i64().unwrap_or(5);
+    if max_fixed_updates < 0 || max_fixed_updates > u32::MAX as i64 {
+      panic!("Error: max_fixed_updates must be at least 0 and fit in 32 bits, got {}", max_fixed_updates)
+    }
+    let max_fixed_updates = max_fixed_updates as u32;
     ConfigSettings::new(title, window_width, window_height, samples, fullscreen,
-      exit_on_esc, vsync, srgb, resizable, decorated, controllers)
+      exit_on_esc, vsync, srgb, resizable, decorated, controllers, fixed_update_rate, max_fixed_updates)
   }
 }
 
@@ -149,6 +180,8 @@ mod config_tests {
     assert_eq!(settings.resizable, true);
     assert_eq!(settings.decorated, true);
     assert_eq!(settings.controllers, true);
+    assert_eq!(settings.fixed_update_rate, 0.0);
+    assert_eq!(settings.max_fixed_updates, 5);
   }
 
   // it should properly load the given settings
@@ -184,5 +217,47 @@ mod config_tests {
     assert_eq!(settings.resizable, true);
     assert_eq!(settings.decorated, true);
     assert_eq!(settings.controllers, true);
+    assert_eq!(settings.fixed_update_rate, 0.0);
+    assert_eq!(settings.max_fixed_updates, 5);
+  }
+
+  // it should read the fixed timestep settings as integers or floats
+  #[test]
+  fn test_load_fixed_timestep() {
+    let file_str =
+    "
+    fixed_update_rate:
+        - 59.94
+    max_fixed_updates:
+        - 3
+    ";
+    let docs = match YamlLoader::load_from_str(file_str) {
+      Err(why) => panic!("Error: Couldn't load YAML docs from string: {}",
+                         why.description()),
+      Ok(docs) => docs,
+    };
+    let config_loader = super::ConfigLoader;
+    let settings = config_loader.read_config(&docs[0]);
+    assert_eq!(settings.fixed_update_rate, 59.94);
+    assert_eq!(settings.max_fixed_updates, 3);
+
+    let docs = YamlLoader::load_from_str("fixed_update_rate:\n  - 60\n").unwrap();
+    let settings = config_loader.read_config(&docs[0]);
+    assert_eq!(settings.fixed_update_rate, 60.0);
+  }
+
+  // it should reject fixed timestep settings below 0 instead of wrapping or ignoring them
+  #[test]
+  #[should_panic(expected = "fixed_update_rate must be")]
+  fn test_negative_fixed_update_rate() {
+    let docs = YamlLoader::load_from_str("fixed_update_rate:\n  - -60\n").unwrap();
+    super::ConfigLoader.read_config(&docs[0]);
+  }
+
+  #[test]
+  #[should_panic(expected = "max_fixed_updates must be")]
+  fn test_negative_max_fixed_updates() {
+    let docs = YamlLoader::load_from_str("max_fixed_updates:\n  - -1\n").unwrap();
+    super::ConfigLoader.read_config(&docs[0]);
   }
 }
\ No newline at end of file
@@ -0,0 +1,113 @@
+use super::{Entity, Component, World};
+
+// Created by World::spawn; every with() registers the component type if the current state hasn't yet
+pub struct EntityBuilder<'a> {
+  world: &'a mut World,
+  entity: Entity
+}
+
+impl<'a> EntityBuilder<'a> {
+  pub fn new(world: &'a mut World) -> EntityBuilder<'a> {
+    let entity = world.create();
+    EntityBuilder {
+      world: world,
+      entity: entity
+    }
+  }
+
+  pub fn with<T: Component>(self, comp: T) -> EntityBuilder<'a> {
+    self.world.ensure_registered::<T>();
+    self.world.add_comp(self.entity, comp);
+    self
+  }
+
+  pub fn build(self) -> Entity {
+    self.entity
+  }
+}
+
+// A tuple of components that can be added to an entity in one go
+pub trait Bundle {
+  fn add_to(self, world: &mut World, e: Entity);
+}
+
+macro_rules! impl_bundle_tuple {
+  ($($name:ident),+) => {
+    impl<$($name: Component),+> Bundle for ($($name,)+) {
+      #[allow(non_snake_case)]
+      fn add_to(self, world: &mut World, e: Entity) {
+        let ($($name,)+) = self;
+        $(
+          world.ensure_registered::<$name>();
+          world.add_comp(e, $name);
+        )+
+      }
+    }
+  }
+}
+
+impl_bundle_tuple!(A);
+impl_bundle_tuple!(A, B);
+impl_bundle_tuple!(A, B, C);
+impl_bundle_tuple!(A, B, C, D);
+impl_bundle_tuple!(A, B, C, D, E);
+impl_bundle_tuple!(A, B, C, D, E, F);
+impl_bundle_tuple!(A, B, C, D, E, F, G);
+impl_bundle_tuple!(A, B, C, D, E, F, G, H);
+
+#[cfg(test)]
+mod builder_tests {
+  use super::super::*;
+
+  // describe: spawning entities with components
+
+  struct Position {
+    x: usize
+  }
+
+  impl Component for Position {
+    type Storage = VecStorage<Self>;
+  }
+
+  struct Velocity {
+    dx: usize
+  }
+
+  impl Component for Velocity {
+    type Storage = HashMapStorage<Self>;
+  }
+
+  // it should create an entity with every component given to the builder
+  #[test]
+  fn test_spawn() {
+    let mut test_world = World::new();
+    let entity = test_world.spawn()
+      .with(Position{ x: 1 })
+      .with(Velocity{ dx: 2 })
+      .build();
+    assert_eq!(test_world.contains(entity), true);
+    assert_eq!(test_world.get::<Position>(entity).map(|pos| pos.x), Some(1));
+    assert_eq!(test_world.get::<Velocity>(entity).map(|vel| vel.dx), Some(2));
+  }
+
+  // it should create an entity from a tuple of components
+  #[test]
+  fn test_spawn_bundle() {
+    let mut test_world = World::new();
+    let entity = test_world.spawn_bundle((Position{ x: 3 }, Velocity{ dx: 4 }));
+    assert_eq!(test_world.get::<Position>(entity).map(|pos| pos.x), Some(3));
+    assert_eq!(test_world.get::<Velocity>(entity).map(|vel| vel.dx), Some(4));
+  }
+
+  // it should keep existing components when a spawned entity uses an already registered type
+  #[test]
+  fn test_spawn_registered() {
+    let mut test_world = World::new();
+    test_world.register_comp::<Position>();
+    let first = test_world.create();
+    test_world.add_comp(first, Position{ x: 5 });
+    let second = test_world.spawn_bundle((Position{ x: 6 },));
+    assert_eq!(test_world.get::<Position>(first).map(|pos| pos.x), Some(5));
+    assert_eq!(test_world.get::<Position>(second).map(|pos| pos.x), Some(6));
+  }
+}
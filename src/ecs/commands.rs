@@ -0,0 +1,165 @@
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use super::{Entity, Component, World, Bundle, Event};
+
+trait Command: Send {
+  fn apply(self: Box<Self>, world: &mut World);
+}
+
+impl<F: FnOnce(&mut World) + Send> Command for F {
+  fn apply(self: Box<Self>, world: &mut World) {
+    (*self)(world)
+  }
+}
+
+// Queue of structural changes that are held back until the next World::maintain.
+// Handles are cheap to clone and don't borrow the World, so they can be used mid-iteration.
+#[derive(Clone)]
+pub struct Commands {
+  queue: Arc<Mutex<Vec<Box<Command>>>>
+}
+
+impl Commands {
+  pub fn new() -> Commands {
+    Commands { queue: Arc::new(Mutex::new(Vec::new())) }
+  }
+
+  fn push<F: FnOnce(&mut World) + Send + 'static>(&self, command: F) {
+    self.queue.lock().expect("Error: Command buffer lock was poisoned").push(Box::new(command));
+  }
+
+  pub fn create(&self) {
+    self.push(|world| { world.create(); });
+  }
+
+  pub fn spawn<B: Bundle + Send + 'static>(&self, bundle: B) {
+    self.push(move |world| { world.spawn_bundle(bundle); });
+  }
+
+  pub fn remove(&self, e: Entity) {
+    self.push(move |world| world.remove(e));
+  }
+
+  // skipped if the entity has been removed by the time the buffer is applied
+  pub fn add_comp<T: Component>(&self, e: Entity, comp: T) {
+    self.push(move |world| {
+      if world.contains(e) {
+        world.add_comp(e, comp);
+      }
+    });
+  }
+
+  pub fn remove_comp<T: Component>(&self, e: Entity) {
+    self.push(move |world| { world.remove_comp::<T>(e); });
+  }
+
+  pub fn send<E: Event>(&self, event: E) {
+    self.push(move |world| world.send(event));
+  }
+
+  pub fn exec<F: FnOnce(&mut World) + Send + 'static>(&self, f: F) {
+    self.push(f);
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.queue.lock().expect("Error: Command buffer lock was poisoned").is_empty()
+  }
+
+  // applies commands in the order they were recorded, including any recorded while applying
+  pub fn apply(&self, world: &mut World) {
+    loop {
+      let commands = {
+        let mut queue = self.queue.lock().expect("Error: Command buffer lock was poisoned");
+        mem::replace(&mut *queue, Vec::new())
+      };
+      if commands.is_empty() {
+        break;
+      }
+      for command in commands {
+        command.apply(world);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod commands_tests {
+  use super::super::*;
+
+  // describe: a deferred command buffer
+
+  struct Health {
+    hp: usize
+  }
+
+  impl Component for Health {
+    type Storage = VecStorage<Self>;
+  }
+
+  struct Particle;
+
+  impl Component for Particle {
+    type Storage = NullStorage<Self>;
+  }
+
+  // it should allow entities to be removed while iterating over components
+  #[test]
+  fn test_remove_while_iterating() {
+    let mut test_world = World::new();
+    let dead = test_world.spawn_bundle((Health{ hp: 0 },));
+    let alive = test_world.spawn_bundle((Health{ hp: 3 },));
+    let commands = test_world.commands();
+    for (&e, health) in test_world.get_comp_mut::<Health>() {
+      if health.hp == 0 {
+        commands.remove(e);
+      }
+    }
+    assert_eq!(test_world.contains(dead), true);
+    test_world.maintain();
+    assert_eq!(test_world.contains(dead), false);
+    assert_eq!(test_world.contains(alive), true);
+    assert_eq!(commands.is_empty(), true);
+  }
+
+  // it should apply recorded changes in order
+  #[test]
+  fn test_apply_in_order() {
+    let mut test_world = World::new();
+    let entity = test_world.spawn_bundle((Health{ hp: 1 },));
+    let commands = test_world.commands();
+    commands.add_comp(entity, Health{ hp: 5 });
+    commands.remove_comp::<Health>(entity);
+    commands.spawn((Particle,));
+    commands.exec(|world| { world.create(); });
+    test_world.maintain();
+    assert_eq!(test_world.has::<Health>(entity), false);
+    assert_eq!(test_world.get_comp::<Particle>().len(), 1);
+    assert_eq!(test_world.iter().count(), 3);
+  }
+
+  // it should skip adding components to entities removed earlier in the buffer
+  #[test]
+  fn test_add_comp_after_remove() {
+    let mut test_world = World::new();
+    let entity = test_world.spawn_bundle((Health{ hp: 1 },));
+    let commands = test_world.commands();
+    commands.remove(entity);
+    commands.add_comp(entity, Health{ hp: 5 });
+    test_world.maintain();
+    assert_eq!(test_world.get_comp::<Health>().len(), 0);
+  }
+
+  // it should also apply commands recorded by other commands
+  #[test]
+  fn test_nested_commands() {
+    let mut test_world = World::new();
+    let commands = test_world.commands();
+    commands.exec(|world| {
+      let commands = world.commands();
+      commands.create();
+    });
+    test_world.maintain();
+    assert_eq!(test_world.iter().count(), 1);
+  }
+}
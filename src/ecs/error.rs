@@ -0,0 +1,52 @@
+use std::error::Error;
+use std::fmt;
+
+use super::Entity;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum EcsError {
+  UnregisteredComponent(&'static str),
+  MissingComponent(Entity, &'static str),
+  DeadEntity(Entity),
+  EmptyStateStack,
+  // the child would end up an ancestor of its own parent
+  HierarchyCycle(Entity, Entity),
+  // a state pushed with Copy inheritance would silently lose these components
+  NotSnapshotEnabled(&'static str),
+  ReadOnlyComponent(&'static str)
+}
+
+impl fmt::Display for EcsError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      EcsError::UnregisteredComponent(name) =>
+        write!(f, "component type {} has not been registered in the current state", name),
+      EcsError::MissingComponent(e, name) =>
+        write!(f, "entity {:?} has no {} component", e, name),
+      EcsError::DeadEntity(e) =>
+        write!(f, "entity {:?} is not alive", e),
+      EcsError::EmptyStateStack =>
+        write!(f, "the ecs world state stack is empty"),
+      EcsError::HierarchyCycle(child, parent) =>
+        write!(f, "entity {:?} can't be attached to {:?}, which is itself or one of its descendants", child, parent),
+      EcsError::NotSnapshotEnabled(name) =>
+        write!(f, "component type {} is not snapshot-enabled, so it can't be copied into a new state", name),
+      EcsError::ReadOnlyComponent(name) =>
+        write!(f, "component type {} is read-only; replace it instead of changing it in place", name)
+    }
+  }
+}
+
+impl Error for EcsError {
+  fn description(&self) -> &str {
+    match *self {
+      EcsError::UnregisteredComponent(_) => "unregistered component type",
+      EcsError::MissingComponent(_, _) => "missing component",
+      EcsError::DeadEntity(_) => "dead entity",
+      EcsError::EmptyStateStack => "empty ecs world state stack",
+      EcsError::HierarchyCycle(_, _) => "hierarchy cycle",
+      EcsError::NotSnapshotEnabled(_) => "component type not snapshot-enabled",
+      EcsError::ReadOnlyComponent(_) => "read-only component type"
+    }
+  }
+}
@@ -0,0 +1,168 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::slice;
+use mopa::Any;
+
+use super::World;
+
+// Any Send + Sync type can be sent as an event; each type gets its own queue
+pub trait Event: Any + Send + Sync {}
+
+impl<T: Any + Send + Sync> Event for T {}
+
+pub trait EventQueue: Any + Send + Sync {
+  fn update(&mut self);
+}
+
+mopafy!(EventQueue);
+
+pub type EventQueues = HashMap<TypeId, Box<EventQueue>>;
+
+// Double buffered: an event survives the World::update_events call after it was sent and is
+// dropped at the one after that, so readers that look once per update never miss anything
+struct Events<E: Event> {
+  // ids keep counting up across updates so readers can remember where they stopped
+  events: Vec<(usize, E)>,
+  next_id: usize,
+  // id of the first event sent since the last update
+  current_start: usize
+}
+
+impl<E: Event> Events<E> {
+  fn new() -> Events<E> {
+    Events {
+      events: Vec::new(),
+      next_id: 0,
+      current_start: 0
+    }
+  }
+
+  fn send(&mut self, event: E) {
+    self.events.push((self.next_id, event));
+    self.next_id += 1;
+  }
+
+  fn read(&self, reader: &mut EventReader<E>) -> EventIter<E> {
+    let first_unread = self.events.iter().position(|&(id, _)| id >= reader.next).unwrap_or(self.events.len());
+    reader.next = self.next_id;
+    EventIter { inner: Some(self.events[first_unread..].iter()) }
+  }
+}
+
+impl<E: Event> EventQueue for Events<E> {
+  fn update(&mut self) {
+    let current_start = self.current_start;
+    self.events.retain(|&(id, _)| id >= current_start);
+    self.current_start = self.next_id;
+  }
+}
+
+// Remembers how far one reader got through a queue; every reader sees every event once
+pub struct EventReader<E: Event> {
+  next: usize,
+  marker: PhantomData<E>
+}
+
+impl<E: Event> EventReader<E> {
+  // starts with the events that are still buffered
+  pub fn new() -> EventReader<E> {
+    EventReader {
+      next: 0,
+      marker: PhantomData
+    }
+  }
+}
+
+pub struct EventIter<'a, E: 'a> {
+  inner: Option<slice::Iter<'a, (usize, E)>>
+}
+
+impl<'a, E: 'a> Iterator for EventIter<'a, E> {
+  type Item = &'a E;
+
+  fn next(&mut self) -> Option<&'a E> {
+    self.inner.as_mut().and_then(|inner| inner.next()).map(|&(_, ref event)| event)
+  }
+}
+
+pub fn read_events<'a, E: Event>(queues: &'a EventQueues, reader: &mut EventReader<E>) -> EventIter<'a, E> {
+  queues.get(&TypeId::of::<E>())
+    .and_then(|queue| queue.downcast_ref::<Events<E>>())
+    .map_or(EventIter { inner: None }, |queue| queue.read(reader))
+}
+
+// Event queues belong to the World rather than a state, so they also carry messages between states
+impl World {
+  pub fn send<E: Event>(&mut self, event: E) {
+    self.events.entry(TypeId::of::<E>())
+      .or_insert_with(|| Box::new(Events::<E>::new()))
+      .downcast_mut::<Events<E>>()
+      .expect("Error: Event queue has the wrong type")
+      .send(event);
+  }
+
+  // events sent since the reader last looked that haven't been dropped yet
+  pub fn read_events<E: Event>(&self, reader: &mut EventReader<E>) -> EventIter<E> {
+    read_events(&self.events, reader)
+  }
+
+  // called on the schedule set with Game::set_event_clearing
+  pub fn update_events(&mut self) {
+    for queue in self.events.values_mut() {
+      queue.update();
+    }
+  }
+}
+
+#[cfg(test)]
+mod events_tests {
+  use super::super::*;
+
+  // describe: event channels
+
+  #[derive(PartialEq, Debug)]
+  struct EnemyDied(usize);
+
+  // it should give each reader every event exactly once
+  #[test]
+  fn test_readers() {
+    let mut test_world = World::new();
+    let mut audio = EventReader::<EnemyDied>::new();
+    let mut ui = EventReader::<EnemyDied>::new();
+    assert_eq!(test_world.read_events(&mut audio).count(), 0);
+    test_world.send(EnemyDied(1));
+    test_world.send(EnemyDied(2));
+    assert_eq!(test_world.read_events(&mut audio).collect::<Vec<_>>(), vec![&EnemyDied(1), &EnemyDied(2)]);
+    test_world.send(EnemyDied(3));
+    assert_eq!(test_world.read_events(&mut audio).collect::<Vec<_>>(), vec![&EnemyDied(3)]);
+    assert_eq!(test_world.read_events(&mut audio).count(), 0);
+    assert_eq!(test_world.read_events(&mut ui).count(), 3);
+  }
+
+  // it should keep events for one update before dropping them
+  #[test]
+  fn test_update_events() {
+    let mut test_world = World::new();
+    let mut late = EventReader::<EnemyDied>::new();
+    test_world.send(EnemyDied(1));
+    test_world.update_events();
+    test_world.send(EnemyDied(2));
+    assert_eq!(test_world.read_events(&mut EventReader::<EnemyDied>::new()).count(), 2);
+    test_world.update_events();
+    assert_eq!(test_world.read_events(&mut late).collect::<Vec<_>>(), vec![&EnemyDied(2)]);
+    test_world.update_events();
+    assert_eq!(test_world.read_events(&mut EventReader::<EnemyDied>::new()).count(), 0);
+  }
+
+  // it should let commands send events once the world is maintained
+  #[test]
+  fn test_send_through_commands() {
+    let mut test_world = World::new();
+    let mut reader = EventReader::<EnemyDied>::new();
+    test_world.commands().send(EnemyDied(4));
+    assert_eq!(test_world.read_events(&mut reader).count(), 0);
+    test_world.maintain();
+    assert_eq!(test_world.read_events(&mut reader).collect::<Vec<_>>(), vec![&EnemyDied(4)]);
+  }
+}
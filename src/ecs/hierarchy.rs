@@ -0,0 +1,266 @@
+use yaml_rust::Yaml;
+
+use super::{Entity, Component, World, EcsError, HashMapStorage, Serializable, EntityMap, entity_to_yaml};
+
+// Points at the entity this one is attached to; kept in sync with the parent's Children by World
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Parent(pub Entity);
+
+impl Component for Parent {
+  type Storage = HashMapStorage<Self>;
+}
+
+// Direct children in the order they were attached
+#[derive(Clone, PartialEq, Debug)]
+pub struct Children(Vec<Entity>);
+
+impl Component for Children {
+  type Storage = HashMapStorage<Self>;
+}
+
+impl Children {
+  pub fn iter(&self) -> ::std::slice::Iter<Entity> {
+    self.0.iter()
+  }
+
+  pub fn as_slice(&self) -> &[Entity] {
+    &self.0
+  }
+}
+
+impl Serializable for Parent {
+  fn serialize(&self) -> Yaml {
+    entity_to_yaml(self.0)
+  }
+
+  fn deserialize(data: &Yaml, entities: &EntityMap) -> Option<Parent> {
+    entities.entity(data).map(Parent)
+  }
+}
+
+impl Serializable for Children {
+  fn serialize(&self) -> Yaml {
+    Yaml::Array(self.0.iter().map(|&child| entity_to_yaml(child)).collect())
+  }
+
+  fn deserialize(data: &Yaml, entities: &EntityMap) -> Option<Children> {
+    data.as_vec()
+      .and_then(|children| children.iter().map(|child| entities.entity(child)).collect())
+      .map(Children)
+  }
+}
+
+// The hierarchy should only be edited through these methods; adding or removing
+// Parent and Children by hand leaves the two sides out of sync
+impl World {
+  pub fn set_parent(&mut self, child: Entity, parent: Entity) {
+    match self.try_set_parent(child, parent) {
+      Ok(()) => (),
+      Err(EcsError::HierarchyCycle(_, _)) => panic!("Error: Cannot attach an entity to itself or one of its descendants"),
+      Err(err) => panic!("Error: Could not attach entity to parent: {:?}", err)
+    }
+  }
+
+  pub fn try_set_parent(&mut self, child: Entity, parent: Entity) -> Result<(), EcsError> {
+    self.check_alive(child)?;
+    self.check_alive(parent)?;
+    if self.is_ancestor(child, parent) {
+      return Err(EcsError::HierarchyCycle(child, parent));
+    }
+    self.detach(child);
+    self.ensure_registered::<Parent>();
+    self.ensure_registered::<Children>();
+    self.add_comp(child, Parent(parent));
+    if self.has::<Children>(parent) {
+      if let Some(children) = self.get_mut::<Children>(parent) {
+        children.0.push(child);
+      }
+    } else {
+      self.add_comp(parent, Children(vec![child]));
+    }
+    Ok(())
+  }
+
+  // detaches the entity from its parent, making it a root; its own children stay attached
+  pub fn remove_parent(&mut self, child: Entity) -> Option<Entity> {
+    if !self.contains(child) {
+      return None;
+    }
+    self.detach(child)
+  }
+
+  pub fn parent(&self, e: Entity) -> Option<Entity> {
+    self.get::<Parent>(e).map(|parent| parent.0)
+  }
+
+  pub fn children(&self, e: Entity) -> &[Entity] {
+    self.get::<Children>(e).map_or(&[], |children| children.as_slice())
+  }
+
+  // the entity followed by all of its descendants, parents always before their children
+  pub fn descendants(&self, e: Entity) -> Vec<Entity> {
+    let mut found = Vec::new();
+    if !self.contains(e) {
+      return found;
+    }
+    let mut pending = vec![e];
+    while let Some(next) = pending.pop() {
+      found.push(next);
+      pending.extend(self.children(next).iter().rev());
+    }
+    found
+  }
+
+  // removes the entity and everything below it, returning the removed entities
+  pub fn remove_recursive(&mut self, e: Entity) -> Vec<Entity> {
+    match self.try_remove_recursive(e) {
+      Ok(removed) => removed,
+      Err(EcsError::DeadEntity(_)) => Vec::new(),
+      Err(_) => panic!("Error: Could not find ecs world state (mut)")
+    }
+  }
+
+  pub fn try_remove_recursive(&mut self, e: Entity) -> Result<Vec<Entity>, EcsError> {
+    self.check_alive(e)?;
+    self.detach(e);
+    let removed = self.descendants(e);
+    for &descendant in &removed {
+      self.despawn(descendant)?;
+    }
+    Ok(removed)
+  }
+
+  fn is_ancestor(&self, ancestor: Entity, e: Entity) -> bool {
+    let mut current = Some(e);
+    while let Some(next) = current {
+      if next == ancestor {
+        return true;
+      }
+      current = self.parent(next);
+    }
+    false
+  }
+
+  fn detach(&mut self, child: Entity) -> Option<Entity> {
+    let parent = self.remove_comp::<Parent>(child).map(|parent| parent.0);
+    if let Some(parent) = parent {
+      let now_empty = match self.get_mut::<Children>(parent) {
+        Some(children) => {
+          children.0.retain(|&sibling| sibling != child);
+          children.0.is_empty()
+        },
+        None => false
+      };
+      if now_empty {
+        self.remove_comp::<Children>(parent);
+      }
+    }
+    parent
+  }
+}
+
+#[cfg(test)]
+mod hierarchy_tests {
+  use super::super::*;
+
+  // describe: parent/child relationships between entities
+
+  // it should link parents and children both ways
+  #[test]
+  fn test_set_parent() {
+    let mut test_world = World::new();
+    let body = test_world.create();
+    let weapon = test_world.create();
+    let hat = test_world.create();
+    test_world.set_parent(weapon, body);
+    test_world.set_parent(hat, body);
+    assert_eq!(test_world.parent(weapon), Some(body));
+    assert_eq!(test_world.parent(body), None);
+    assert_eq!(test_world.children(body), &[weapon, hat]);
+    assert_eq!(test_world.children(weapon), &[]);
+  }
+
+  // it should move a child from its old parent when reparented
+  #[test]
+  fn test_reparent() {
+    let mut test_world = World::new();
+    let first = test_world.create();
+    let second = test_world.create();
+    let child = test_world.create();
+    test_world.set_parent(child, first);
+    test_world.set_parent(child, second);
+    assert_eq!(test_world.children(first), &[]);
+    assert_eq!(test_world.has::<Children>(first), false);
+    assert_eq!(test_world.children(second), &[child]);
+    assert_eq!(test_world.remove_parent(child), Some(second));
+    assert_eq!(test_world.parent(child), None);
+    assert_eq!(test_world.children(second), &[]);
+  }
+
+  // it should remove all descendants along with their root
+  #[test]
+  fn test_remove_recursive() {
+    let mut test_world = World::new();
+    let root = test_world.create();
+    let body = test_world.create();
+    let weapon = test_world.create();
+    let hat = test_world.create();
+    let bystander = test_world.create();
+    test_world.set_parent(body, root);
+    test_world.set_parent(weapon, body);
+    test_world.set_parent(hat, body);
+    assert_eq!(test_world.remove_recursive(body), vec![body, weapon, hat]);
+    assert_eq!(test_world.contains(hat), false);
+    assert_eq!(test_world.children(root), &[]);
+    test_world.remove(root);
+    assert_eq!(test_world.iter().collect::<Vec<_>>(), vec![&bystander]);
+    assert_eq!(test_world.remove_recursive(root), vec![]);
+  }
+
+  // it should remove descendants when a parent is removed through World::remove
+  #[test]
+  fn test_remove_parent_entity() {
+    let mut test_world = World::new();
+    let body = test_world.create();
+    let weapon = test_world.create();
+    test_world.set_parent(weapon, body);
+    test_world.remove(body);
+    assert_eq!(test_world.contains(weapon), false);
+    assert_eq!(test_world.removed::<Parent>().collect::<Vec<_>>(), vec![weapon]);
+  }
+
+  // it should refuse to create cycles
+  #[test]
+  #[should_panic(expected = "Error: Cannot attach an entity to itself or one of its descendants")]
+  fn test_cycle() {
+    let mut test_world = World::new();
+    let parent = test_world.create();
+    let child = test_world.create();
+    test_world.set_parent(child, parent);
+    test_world.set_parent(parent, child);
+  }
+
+  // it should report cycles from try_set_parent and leave the hierarchy as it was
+  #[test]
+  fn test_try_cycle() {
+    let mut test_world = World::new();
+    let parent = test_world.create();
+    let child = test_world.create();
+    test_world.set_parent(child, parent);
+    assert_eq!(test_world.try_set_parent(parent, child), Err(EcsError::HierarchyCycle(parent, child)));
+    assert_eq!(test_world.try_set_parent(parent, parent), Err(EcsError::HierarchyCycle(parent, parent)));
+    assert_eq!(test_world.parent(child), Some(parent));
+    assert_eq!(test_world.parent(parent), None);
+  }
+
+  // it should report dead entities from the try_ variants
+  #[test]
+  fn test_try_dead() {
+    let mut test_world = World::new();
+    let parent = test_world.create();
+    let child = test_world.create();
+    test_world.remove(child);
+    assert_eq!(test_world.try_set_parent(child, parent), Err(EcsError::DeadEntity(child)));
+    assert_eq!(test_world.try_remove_recursive(child), Err(EcsError::DeadEntity(child)));
+  }
+}
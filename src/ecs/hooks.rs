@@ -0,0 +1,308 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::{Entity, Component, World};
+
+type EntityHook = Arc<Fn(&mut World, Entity) + Send + Sync>;
+type RemoveHook<T> = Arc<Fn(&mut World, Entity, &T) + Send + Sync>;
+// takes a component with on_remove hooks off an entity being removed and runs the hooks
+type TakeComponent = fn(&mut World, Entity);
+// runs the on_add hooks of a component type without knowing it
+type RunAdded = fn(&mut World, Entity);
+
+struct ComponentHooks<T> {
+  on_add: Vec<EntityHook>,
+  on_remove: Vec<RemoveHook<T>>
+}
+
+// Callbacks registered on the World; like the event queues they apply to every state
+pub struct Hooks {
+  components: HashMap<TypeId, Box<Any + Send + Sync>>,
+  // in the order the types got their first on_remove hook, which is the order they're taken in
+  takers: Vec<(TypeId, TakeComponent)>,
+  // likewise for on_add hooks
+  adders: Vec<(TypeId, RunAdded)>,
+  entity_created: Vec<EntityHook>,
+  entity_removed: Vec<EntityHook>
+}
+
+fn run_added<T: Component>(world: &mut World, e: Entity) {
+  world.run_on_add::<T>(e);
+}
+
+fn take_component<T: Component>(world: &mut World, e: Entity) {
+  let tick = world.change_tick;
+  let comp = world.store_mut::<T>().and_then(|typed_store| typed_store.remove(e, tick));
+  if let Some(comp) = comp {
+    world.run_on_remove(e, &comp);
+  }
+}
+
+impl Hooks {
+  pub fn new() -> Hooks {
+    Hooks {
+      components: HashMap::new(),
+      takers: Vec::new(),
+      adders: Vec::new(),
+      entity_created: Vec::new(),
+      entity_removed: Vec::new()
+    }
+  }
+
+  fn typed<T: Component>(&self) -> Option<&ComponentHooks<T>> {
+    self.components.get(&TypeId::of::<T>())
+      .and_then(|hooks| hooks.downcast_ref::<ComponentHooks<T>>())
+  }
+
+  fn typed_mut<T: Component>(&mut self) -> &mut ComponentHooks<T> {
+    self.components.entry(TypeId::of::<T>())
+      .or_insert_with(|| Box::new(ComponentHooks::<T> { on_add: Vec::new(), on_remove: Vec::new() }))
+      .downcast_mut::<ComponentHooks<T>>()
+      .expect("Error: Component hooks have the wrong type")
+  }
+}
+
+// Hooks run right away with full access to the World, so they may add, remove and spawn.
+// Hooks are cloned out before they run, which lets them register further hooks.
+impl World {
+  // runs after a T is added to an entity; replacing a T counts as removing the old one first
+  pub fn on_add<T: Component, F: Fn(&mut World, Entity) + Send + Sync + 'static>(&mut self, hook: F) {
+    self.hooks.typed_mut::<T>().on_add.push(Arc::new(hook));
+    if !self.hooks.adders.iter().any(|&(ty, _)| ty == TypeId::of::<T>()) {
+      self.hooks.adders.push((TypeId::of::<T>(), run_added::<T>));
+    }
+  }
+
+  // runs with the removed T, including when the whole entity is removed
+  pub fn on_remove<T: Component, F: Fn(&mut World, Entity, &T) + Send + Sync + 'static>(&mut self, hook: F) {
+    self.hooks.typed_mut::<T>().on_remove.push(Arc::new(hook));
+    if !self.hooks.takers.iter().any(|&(ty, _)| ty == TypeId::of::<T>()) {
+      self.hooks.takers.push((TypeId::of::<T>(), take_component::<T>));
+    }
+  }
+
+  // runs before any components are added, e.g. by spawn_bundle
+  pub fn on_entity_created<F: Fn(&mut World, Entity) + Send + Sync + 'static>(&mut self, hook: F) {
+    self.hooks.entity_created.push(Arc::new(hook));
+  }
+
+  // runs once the entity and all its components are gone, for every removed descendant too
+  pub fn on_entity_removed<F: Fn(&mut World, Entity) + Send + Sync + 'static>(&mut self, hook: F) {
+    self.hooks.entity_removed.push(Arc::new(hook));
+  }
+
+  // entities a Copy state inherited were added below, so they aren't removed from here either
+  fn runs_hooks(&self, e: Entity) -> bool {
+    self.world_state_stack.last().map_or(true, |world_state| !world_state.inherited.contains(&e))
+  }
+
+  pub(super) fn run_on_add<T: Component>(&mut self, e: Entity) {
+    let hooks = match self.hooks.typed::<T>() {
+      Some(typed_hooks) if !typed_hooks.on_add.is_empty() && self.runs_hooks(e) => typed_hooks.on_add.clone(),
+      _ => return
+    };
+    for hook in hooks {
+      hook(self, e);
+    }
+  }
+
+  pub(super) fn run_on_remove<T: Component>(&mut self, e: Entity, comp: &T) {
+    let hooks = match self.hooks.typed::<T>() {
+      Some(typed_hooks) if !typed_hooks.on_remove.is_empty() && self.runs_hooks(e) => typed_hooks.on_remove.clone(),
+      _ => return
+    };
+    for hook in hooks {
+      hook(self, e, comp);
+    }
+  }
+
+  pub(super) fn run_entity_created(&mut self, e: Entity) {
+    let hooks = self.hooks.entity_created.clone();
+    for hook in hooks {
+      hook(self, e);
+    }
+  }
+
+  pub(super) fn run_entity_removed(&mut self, e: Entity) {
+    if !self.runs_hooks(e) {
+      return;
+    }
+    let hooks = self.hooks.entity_removed.clone();
+    for hook in hooks {
+      hook(self, e);
+    }
+  }
+
+  // takes every component type with on_remove hooks off e before the remaining stores are cleared
+  pub(super) fn take_hooked(&mut self, e: Entity) {
+    self.take_hooked_where(e, |_| true);
+  }
+
+  // like take_hooked, for just the component types pick chooses
+  pub(super) fn take_hooked_where<F: Fn(TypeId) -> bool>(&mut self, e: Entity, pick: F) {
+    let takers: Vec<TakeComponent> = self.hooks.takers.iter()
+      .filter(|&&(ty, _)| pick(ty))
+      .map(|&(_, take)| take)
+      .collect();
+    for take in takers {
+      take(self, e);
+    }
+  }
+
+  // the component types with on_add hooks, in registration order
+  pub(super) fn hooked_additions(&self) -> Vec<TypeId> {
+    self.hooks.adders.iter().map(|&(ty, _)| ty).collect()
+  }
+
+  // runs the on_add hooks of the component types pick chooses for e, which must have them
+  pub(super) fn run_added_where<F: Fn(TypeId) -> bool>(&mut self, e: Entity, pick: F) {
+    let adders: Vec<RunAdded> = self.hooks.adders.iter()
+      .filter(|&&(ty, _)| pick(ty))
+      .map(|&(_, run)| run)
+      .collect();
+    for run in adders {
+      run(self, e);
+    }
+  }
+}
+
+#[cfg(test)]
+mod hooks_tests {
+  use std::sync::{Arc, Mutex};
+  use super::super::*;
+
+  // describe: lifecycle hooks
+
+  #[derive(Clone)]
+  struct RigidBody {
+    handle: usize
+  }
+
+  impl Component for RigidBody {
+    type Storage = VecStorage<Self>;
+  }
+
+  // stands in for a physics engine the bodies are registered with
+  struct Physics {
+    bodies: Vec<usize>
+  }
+
+  fn physics_world() -> World {
+    let mut test_world = World::new();
+    test_world.register_comp::<RigidBody>();
+    test_world.insert_global_resource(Physics { bodies: Vec::new() });
+    test_world.on_add::<RigidBody, _>(|world, e| {
+      let handle = world.get::<RigidBody>(e).unwrap().handle;
+      world.resource_mut::<Physics>().unwrap().bodies.push(handle);
+    });
+    test_world.on_remove::<RigidBody, _>(|world, _, body| {
+      world.resource_mut::<Physics>().unwrap().bodies.retain(|&handle| handle != body.handle);
+    });
+    test_world
+  }
+
+  fn bodies(world: &World) -> Vec<usize> {
+    world.resource::<Physics>().unwrap().bodies.clone()
+  }
+
+  // it should run component hooks on add, replace and remove
+  #[test]
+  fn test_component_hooks() {
+    let mut test_world = physics_world();
+    let crate_entity = test_world.create();
+    test_world.add_comp(crate_entity, RigidBody { handle: 1 });
+    let ball = test_world.spawn_bundle((RigidBody { handle: 2 },));
+    assert_eq!(bodies(&test_world), vec![1, 2]);
+    test_world.add_comp(ball, RigidBody { handle: 3 });
+    assert_eq!(bodies(&test_world), vec![1, 3]);
+    test_world.remove_comp::<RigidBody>(crate_entity);
+    assert_eq!(bodies(&test_world), vec![3]);
+    assert!(test_world.try_remove_comp::<RigidBody>(crate_entity).is_err());
+  }
+
+  // it should run on_remove hooks for every component of removed entities and their descendants
+  #[test]
+  fn test_remove_entity() {
+    let mut test_world = physics_world();
+    let cart = test_world.spawn_bundle((RigidBody { handle: 1 },));
+    let wheel = test_world.spawn_bundle((RigidBody { handle: 2 },));
+    test_world.set_parent(wheel, cart);
+    test_world.spawn_bundle((RigidBody { handle: 3 },));
+    test_world.remove(cart);
+    assert_eq!(bodies(&test_world), vec![3]);
+    assert_eq!(test_world.get_comp::<RigidBody>().len(), 1);
+  }
+
+  // it should tell observers about created and removed entities
+  #[test]
+  fn test_entity_observers() {
+    let mut test_world = World::new();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let created_log = log.clone();
+    test_world.on_entity_created(move |_, e| created_log.lock().unwrap().push(("created", e)));
+    let removed_log = log.clone();
+    test_world.on_entity_removed(move |world, e| {
+      assert_eq!(world.contains(e), false);
+      removed_log.lock().unwrap().push(("removed", e));
+    });
+    let first = test_world.create();
+    let second = test_world.create();
+    test_world.remove(first);
+    test_world.commands().remove(second);
+    test_world.maintain();
+    assert_eq!(*log.lock().unwrap(), vec![("created", first), ("created", second), ("removed", first), ("removed", second)]);
+  }
+
+  // it should let hooks change the world
+  #[test]
+  fn test_reentrant_hooks() {
+    let mut test_world = World::new();
+    test_world.on_entity_created(|world, e| world.set_name(e, "spawned"));
+    test_world.on_remove::<Name, _>(|world, e, _| world.remove(e));
+    let named = test_world.create();
+    assert_eq!(test_world.name(named), Some("spawned"));
+    test_world.remove_comp::<Name>(named);
+    assert_eq!(test_world.contains(named), false);
+  }
+
+  // it should run the removal hooks of a popped state's entities, in id and registration order
+  #[test]
+  fn test_pop_state() {
+    let mut test_world = physics_world();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let body_log = log.clone();
+    test_world.on_remove::<RigidBody, _>(move |_, e, _| body_log.lock().unwrap().push(("body", e)));
+    let name_log = log.clone();
+    test_world.on_remove::<Name, _>(move |_, e, _| name_log.lock().unwrap().push(("name", e)));
+    let removed_log = log.clone();
+    test_world.on_entity_removed(move |_, e| removed_log.lock().unwrap().push(("removed", e)));
+    test_world.spawn_bundle((RigidBody { handle: 1 },));
+    push_state(&mut test_world);
+    let crate_entity = test_world.spawn_bundle((RigidBody { handle: 2 }, Name::new("crate")));
+    let ball = test_world.spawn_bundle((RigidBody { handle: 3 },));
+    assert_eq!(bodies(&test_world), vec![1, 2, 3]);
+    pop_state(&mut test_world);
+    assert_eq!(bodies(&test_world), vec![1]);
+    assert_eq!(*log.lock().unwrap(), vec![("body", crate_entity), ("name", crate_entity), ("removed", crate_entity),
+      ("body", ball), ("removed", ball)]);
+  }
+
+  // it should run no hooks for the entities a Copy state carried over, which live on below it
+  #[test]
+  fn test_pop_copied_state() {
+    let mut test_world = physics_world();
+    test_world.register_snapshot::<RigidBody>();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let removed_log = log.clone();
+    test_world.on_entity_removed(move |_, e| removed_log.lock().unwrap().push(e));
+    let cart = test_world.spawn_bundle((RigidBody { handle: 1 },));
+    push_state_with(&mut test_world, StateInheritance::Copy);
+    test_world.add_comp(cart, RigidBody { handle: 4 });
+    let ball = test_world.spawn_bundle((RigidBody { handle: 2 },));
+    assert_eq!(bodies(&test_world), vec![1, 2]);
+    pop_state(&mut test_world);
+    assert_eq!(bodies(&test_world), vec![1]);
+    assert_eq!(*log.lock().unwrap(), vec![ball]);
+  }
+}
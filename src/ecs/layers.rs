@@ -0,0 +1,241 @@
+use std::mem;
+
+use super::{World, WorldState, EcsError, pop_state};
+
+// What a newly pushed state gets to see of the state below it
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum StateInheritance {
+  // starts out empty, like push_state
+  Empty,
+  // starts out empty, but can read the state below through World::below
+  ReadOnly,
+  // starts out as an eager copy of the state below, taken the same way as World::snapshot;
+  // changes stay in the new state and are dropped when it is popped. Every component the state
+  // below holds has to be snapshot-enabled, while other resources are simply left behind.
+  // The copied entities run no hooks in the new state, not even when it is popped.
+  Copy
+}
+
+pub fn push_state_with(world: &mut World, inheritance: StateInheritance) {
+  try_push_state_with(world, inheritance).unwrap_or_else(|err| panic!("Error: Could not push ecs world state: {}", err))
+}
+
+pub fn try_push_state_with(world: &mut World, inheritance: StateInheritance) -> Result<(), EcsError> {
+  let mut world_state = match inheritance {
+    // the bottom state has nothing to copy
+    StateInheritance::Copy if !world.world_state_stack.is_empty() => {
+      let below = world.current_state();
+      let uncopied = below.components.iter()
+        .find(|&(key, store)| store.len() > 0 && !world.snapshot_types.has_component(&key.1));
+      if let Some((_, store)) = uncopied {
+        return Err(EcsError::NotSnapshotEnabled(store.type_name()));
+      }
+      let mut world_state = world.snapshot().into_state();
+      world_state.inherited = world_state.active.clone();
+      world_state
+    },
+    _ => WorldState::new()
+  };
+  world_state.inheritance = inheritance;
+  world.world_state_stack.push(world_state);
+  Ok(())
+}
+
+// Runs f with the state depth levels below the top as the current one, for games that keep
+// updating or drawing states covered by an overlay. f must leave the stack as it found it.
+pub fn with_covered_state<R, F: FnOnce(&mut World) -> R>(world: &mut World, depth: usize, f: F) -> R {
+  let len = world.world_state_stack.len();
+  if depth >= len {
+    panic!("Error: Attempted to reach below the bottom of the ecs world state stack")
+  }
+  let above = world.world_state_stack.split_off(len - depth);
+  let result = f(world);
+  world.world_state_stack.extend(above);
+  result
+}
+
+// Drops the state depth levels below the top, e.g. once a transition has moved past it;
+// like pop_state, it runs the removal hooks of the state's entities
+pub fn remove_covered_state(world: &mut World, depth: usize) {
+  with_covered_state(world, depth, |world| {
+    pop_state(world);
+  });
+}
+
+// Runs f with the top state taken off the stack, then puts it back where it was, below whatever
+// f pushed; a state pushed in f inherits from the one below the top, as if the top had been popped
+pub fn with_top_state_aside<R, F: FnOnce(&mut World) -> R>(world: &mut World, f: F) -> R {
+  let top = world.world_state_stack.pop().expect("Error: Could not find ecs world state (mut)");
+  let index = world.world_state_stack.len();
+  let result = f(world);
+  if index > world.world_state_stack.len() {
+    panic!("Error: States below the one set aside were popped")
+  }
+  world.world_state_stack.insert(index, top);
+  result
+}
+
+impl World {
+  // Runs f against the shared layer, whose entities outlive every state on the stack; use it for
+  // things like the HUD or the music player. Entity handles from the shared layer are only valid
+  // inside shared and are told apart by Entity::is_shared. Resources inserted here are global resources.
+  pub fn shared<R, F: FnOnce(&mut World) -> R>(&mut self, f: F) -> R {
+    let mut shared_state = mem::replace(&mut self.shared_state, WorldState::new());
+    mem::swap(&mut shared_state.resources, &mut self.global_resources);
+    self.world_state_stack.push(shared_state);
+    let result = f(self);
+    let mut shared_state = self.world_state_stack.pop().expect("Error: Shared layer was popped from the state stack");
+    mem::swap(&mut shared_state.resources, &mut self.global_resources);
+    self.shared_state = shared_state;
+    result
+  }
+
+  // Lets a state pushed with ReadOnly or Copy inheritance look at the state below it.
+  // Returns None for any other state.
+  pub fn below<R, F: FnOnce(&World) -> R>(&mut self, f: F) -> Option<R> {
+    let depth = self.world_state_stack.len();
+    if depth < 2 || self.current_state().inheritance == StateInheritance::Empty {
+      return None;
+    }
+    let current = self.world_state_stack.pop().expect("Error: Could not find ecs world state (mut)");
+    let result = f(self);
+    self.world_state_stack.push(current);
+    Some(result)
+  }
+}
+
+#[cfg(test)]
+mod layers_tests {
+  use super::super::*;
+
+  // describe: the shared layer and inherited states
+
+  #[derive(Clone, PartialEq, Debug)]
+  struct Score(usize);
+
+  impl Component for Score {
+    type Storage = HashMapStorage<Self>;
+  }
+
+  struct Music;
+
+  // it should keep shared entities and resources across pushes, pops and switches
+  #[test]
+  fn test_shared_layer() {
+    let mut test_world = World::new();
+    let hud = test_world.shared(|shared| {
+      shared.insert_resource(Music);
+      shared.spawn_bundle((Name::new("hud"),))
+    });
+    push_state(&mut test_world);
+    switch_state(&mut test_world);
+    assert_eq!(test_world.find_by_name("hud"), None);
+    assert_eq!(test_world.shared(|shared| shared.find_by_name("hud")), Some(hud));
+    assert!(test_world.resource::<Music>().is_some());
+    pop_state(&mut test_world);
+    assert_eq!(test_world.shared(|shared| shared.contains(hud)), true);
+  }
+
+  // it should let a read-only state see the state below it
+  #[test]
+  fn test_read_only() {
+    let mut test_world = World::new();
+    let player = test_world.spawn_bundle((Score(3),));
+    push_state(&mut test_world);
+    assert_eq!(test_world.below(|below| below.contains(player)), None);
+    pop_state(&mut test_world);
+    push_state_with(&mut test_world, StateInheritance::ReadOnly);
+    assert_eq!(test_world.contains(player), false);
+    assert_eq!(test_world.below(|below| below.get::<Score>(player).cloned()), Some(Some(Score(3))));
+  }
+
+  // it should start a copying state from the snapshot-enabled part of the state below
+  #[test]
+  fn test_copy() {
+    let mut test_world = World::new();
+    test_world.register_snapshot::<Score>();
+    let player = test_world.spawn_bundle((Score(3), Transform::at(1.0, 1.0)));
+    push_state_with(&mut test_world, StateInheritance::Copy);
+    assert_eq!(test_world.get::<Score>(player), Some(&Score(3)));
+    test_world.get_mut::<Score>(player).unwrap().0 = 10;
+    test_world.spawn_bundle((Score(1),));
+    assert_eq!(test_world.below(|below| below.get::<Score>(player).cloned()), Some(Some(Score(3))));
+    pop_state(&mut test_world);
+    assert_eq!(test_world.get::<Score>(player), Some(&Score(3)));
+    assert_eq!(test_world.iter().count(), 1);
+  }
+
+  // it should refuse to copy a state holding components that aren't snapshot-enabled
+  #[test]
+  fn test_copy_unregistered() {
+    let mut test_world = World::new();
+    test_world.spawn_bundle((Score(3),));
+    assert_eq!(try_push_state_with(&mut test_world, StateInheritance::Copy),
+      Err(EcsError::NotSnapshotEnabled(any::type_name::<Score>())));
+    pop_state(&mut test_world);
+    assert!(try_pop_state(&mut test_world).is_err());
+  }
+
+  // it should keep shared layer handles apart from the state's
+  #[test]
+  fn test_shared_handles() {
+    let mut test_world = World::new();
+    let player = test_world.create();
+    let hud = test_world.shared(|shared| shared.create());
+    assert_eq!((player.id(), hud.id()), (0, 0));
+    assert!(hud.is_shared() && !player.is_shared());
+    assert_eq!(test_world.contains(hud), false);
+    assert_eq!(test_world.shared(|shared| shared.contains(player)), false);
+  }
+
+  // it should run against a covered state and put the states above it back
+  #[test]
+  fn test_with_covered_state() {
+    let mut test_world = World::new();
+    let game_entity = test_world.spawn_bundle((Score(3),));
+    push_state(&mut test_world);
+    test_world.create();
+    test_world.create();
+    let covered_count = with_covered_state(&mut test_world, 1, |world| {
+      world.get_mut::<Score>(game_entity).unwrap().0 += 1;
+      world.iter().count()
+    });
+    assert_eq!(covered_count, 1);
+    assert_eq!(test_world.iter().count(), 2);
+    assert_eq!(with_covered_state(&mut test_world, 0, |world| world.iter().count()), 2);
+    pop_state(&mut test_world);
+    assert_eq!(test_world.get::<Score>(game_entity), Some(&Score(4)));
+  }
+
+  // it should drop a covered state and keep the ones above it
+  #[test]
+  fn test_remove_covered_state() {
+    let mut test_world = World::new();
+    test_world.create();
+    push_state(&mut test_world);
+    test_world.create();
+    test_world.create();
+    remove_covered_state(&mut test_world, 1);
+    assert_eq!(test_world.iter().count(), 2);
+    pop_state(&mut test_world);
+    assert!(try_pop_state(&mut test_world).is_err());
+  }
+
+  // it should push under the set aside state as if it had been popped, then put it back on top
+  #[test]
+  fn test_with_top_state_aside() {
+    let mut test_world = World::new();
+    test_world.register_snapshot::<Score>();
+    test_world.create();
+    let player = test_world.spawn_bundle((Score(3),));
+    push_state(&mut test_world);
+    let leaving = test_world.spawn_bundle((Score(1),));
+    with_top_state_aside(&mut test_world, |world| push_state_with(world, StateInheritance::Copy));
+    assert_eq!(test_world.get::<Score>(player), Some(&Score(3)));
+    assert_eq!(test_world.has::<Score>(leaving), false);
+    assert_eq!(with_covered_state(&mut test_world, 1, |world| world.get::<Score>(leaving).cloned()), Some(Score(1)));
+    remove_covered_state(&mut test_world, 1);
+    pop_state(&mut test_world);
+    assert_eq!(test_world.get::<Score>(player), Some(&Score(3)));
+  }
+}
@@ -0,0 +1,1178 @@
+use std::any::{self, TypeId};
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_set;
+use std::rc::Rc;
+use mopa::Any;
+use rayon::prelude::*;
+
+use std::usize;
+
+mod query;
+pub use self::query::{Query, QueryIter, With, Without, Added, Changed};
+mod tracking;
+pub use self::tracking::{ChangeTicks, RemovedIter};
+use self::tracking::ComponentTicks;
+mod storage;
+pub use self::storage::{Storage, StorageIter, StorageIterMut, HashMapStorage, VecStorage, VecIter, VecIterMut, SparseSetStorage, NullStorage};
+mod system;
+pub use self::system::{System, Dispatcher, Stage};
+mod parallel;
+pub use self::parallel::{ParSystem, ParDispatcher, SystemData};
+mod builder;
+pub use self::builder::{EntityBuilder, Bundle};
+mod error;
+pub use self::error::EcsError;
+mod commands;
+pub use self::commands::Commands;
+mod hierarchy;
+pub use self::hierarchy::{Parent, Children};
+mod transform;
+pub use self::transform::{Transform, GlobalTransform};
+mod serialize;
+pub use self::serialize::{Serializable, EntityMap, WorldSerializer, SaveFormat, SerializeError};
+pub use self::serialize::{entity_to_yaml, float_to_yaml, yaml_to_float};
+mod prefab;
+pub use self::prefab::Prefabs;
+mod snapshot;
+pub use self::snapshot::WorldSnapshot;
+use self::snapshot::SnapshotTypes;
+mod name;
+pub use self::name::{Name, NameStorage, TagIter};
+mod events;
+pub use self::events::{Event, EventReader, EventIter};
+use self::events::EventQueues;
+mod layers;
+pub use self::layers::{StateInheritance, push_state_with, try_push_state_with, with_covered_state, remove_covered_state, with_top_state_aside};
+mod hooks;
+use self::hooks::Hooks;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct Entity(pub usize, pub usize);
+
+impl Entity {
+  pub fn id(&self) -> usize {
+    self.0
+  }
+
+  // bumped every time the id is recycled, so stale handles to a removed entity never match the new one
+  pub fn generation(&self) -> usize {
+    self.1
+  }
+
+  // whether the entity lives in the shared layer rather than in one of the states
+  pub fn is_shared(&self) -> bool {
+    self.1 & SHARED_GENERATION != 0
+  }
+}
+
+// set on the generation of every shared layer entity, so its handles never match a state's entities
+const SHARED_GENERATION: usize = !(usize::MAX >> 1);
+
+pub trait Component: Any + Send + Sync + Sized {
+  type Storage: Storage<Self>;
+  // read-only components can't be borrowed mutably, by get_mut, get_comp_mut or &mut queries,
+  // and are changed by adding a new one instead; e.g. Name, which its storage indexes by value
+  const READ_ONLY: bool = false;
+}
+
+fn check_writable<T: Component>() -> Result<(), EcsError> {
+  if T::READ_ONLY {
+    Err(EcsError::ReadOnlyComponent(any::type_name::<T>()))
+  } else {
+    Ok(())
+  }
+}
+
+fn assert_writable<T: Component>() {
+  if let Err(err) = check_writable::<T>() {
+    panic!("Error: Could not borrow component mutably: {}", err)
+  }
+}
+
+pub trait Store: Any + Send + Sync {
+  fn store_remove(&mut self, e: Entity, tick: usize);
+  fn prune_removed(&mut self, before: usize);
+  fn len(&self) -> usize;
+  fn type_name(&self) -> &'static str;
+  fn has(&self, e: Entity) -> bool;
+}
+
+mopafy!(Store);
+
+// Any Send + Sync type can be stored in the World as a resource; there is at most one of each type
+pub trait Resource: Any + Send + Sync {}
+
+mopafy!(Resource);
+
+impl<T: Any + Send + Sync> Resource for T {}
+
+type Resources = HashMap<TypeId, Box<Resource>>;
+
+pub struct ComponentStore<T: Component> {
+  data: T::Storage,
+  // indexed by entity id; only meaningful for entities the store currently holds
+  ticks: Vec<ComponentTicks>,
+  removed: Vec<(Entity, usize)>
+}
+
+impl<T: Component> Store for ComponentStore<T> {
+  fn store_remove(&mut self, e: Entity, tick: usize) {
+    self.remove(e, tick);
+  }
+
+  fn prune_removed(&mut self, before: usize) {
+    self.removed.retain(|&(_, tick)| tick >= before);
+  }
+
+  fn len(&self) -> usize {
+    self.data.len()
+  }
+
+  fn type_name(&self) -> &'static str {
+    any::type_name::<T>()
+  }
+
+  fn has(&self, e: Entity) -> bool {
+    self.contains(e)
+  }
+}
+
+// only components whose storage can be cloned can take part in snapshots
+impl<T: Component> Clone for ComponentStore<T> where T::Storage: Clone {
+  fn clone(&self) -> ComponentStore<T> {
+    ComponentStore {
+      data: self.data.clone(),
+      ticks: self.ticks.clone(),
+      removed: self.removed.clone()
+    }
+  }
+}
+
+// Handing out &mut T counts as a change, whether or not the caller writes through it
+impl<T: Component> ComponentStore<T> {
+  fn new() -> ComponentStore<T> {
+    ComponentStore {
+      data: T::Storage::new(),
+      ticks: Vec::new(),
+      removed: Vec::new()
+    }
+  }
+
+  fn insert(&mut self, e: Entity, comp: T, tick: usize) -> Option<T> {
+    while self.ticks.len() <= e.0 {
+      self.ticks.push(ComponentTicks::new(0));
+    }
+    let old = self.data.insert(e, comp);
+    match old {
+      Some(_) => self.ticks[e.0].changed = tick,
+      None => self.ticks[e.0] = ComponentTicks::new(tick)
+    }
+    old
+  }
+
+  fn get(&self, e: Entity) -> Option<&T> {
+    self.data.get(e)
+  }
+
+  fn get_mut(&mut self, e: Entity, tick: usize) -> Option<&mut T> {
+    let comp = self.data.get_mut(e);
+    if comp.is_some() {
+      self.ticks[e.0].changed = tick;
+    }
+    comp
+  }
+
+  // like get_mut, but reached through raw pointers only, so components of other entities that
+  // a query already handed out stay valid
+  unsafe fn get_ptr(this: *mut ComponentStore<T>, e: Entity, tick: usize) -> Option<*mut T> {
+    let comp = T::Storage::get_ptr(&mut (*this).data, e);
+    if comp.is_some() {
+      (*(*this).ticks.as_mut_ptr().add(e.0)).changed = tick;
+    }
+    comp
+  }
+
+  fn contains(&self, e: Entity) -> bool {
+    self.data.contains(e)
+  }
+
+  fn entities(&self) -> &[Entity] {
+    self.data.entities()
+  }
+
+  fn is_added(&self, e: Entity, since: usize) -> bool {
+    self.contains(e) && self.ticks[e.0].added > since
+  }
+
+  fn is_changed(&self, e: Entity, since: usize) -> bool {
+    self.contains(e) && self.ticks[e.0].changed > since
+  }
+
+  fn iter(&self) -> StorageIter<T, T::Storage> {
+    StorageIter::new(&self.data)
+  }
+
+  fn iter_mut(&mut self, tick: usize) -> StorageIterMut<T, T::Storage> {
+    for e in self.data.entities() {
+      self.ticks[e.0].changed = tick;
+    }
+    StorageIterMut::new(&mut self.data)
+  }
+
+  fn remove(&mut self, e: Entity, tick: usize) -> Option<T> {
+    let comp = self.data.remove(e);
+    if comp.is_some() {
+      self.removed.push((e, tick));
+    }
+    comp
+  }
+
+  fn removed(&self, since: usize) -> RemovedIter {
+    RemovedIter::new(&self.removed, since)
+  }
+}
+
+struct WorldState {
+  current_id: usize,
+  reusable_ids: Vec<usize>,
+  generations: Vec<usize>,
+  active: HashSet<Entity>,
+  components: HashMap<((), TypeId), Box<Store>>,
+  resources: Resources,
+  inheritance: StateInheritance,
+  // the generation freshly allocated ids start at
+  first_generation: usize,
+  // ids a restore brought back below a generation that was already handed out;
+  // once removed they skip ahead to it, so stale handles stay dead
+  generation_floors: HashMap<usize, usize>,
+  // entities carried over by Copy inheritance; their hooks already ran below, so none run here
+  inherited: HashSet<Entity>
+}
+
+impl WorldState {
+  fn new() -> WorldState {
+    let mut world_state = WorldState {
+      current_id: 0,
+      reusable_ids: Vec::new(),
+      generations: Vec::new(),
+      active: HashSet::new(),
+      components: HashMap::new(),
+      resources: HashMap::new(),
+      inheritance: StateInheritance::Empty,
+      first_generation: 0,
+      generation_floors: HashMap::new(),
+      inherited: HashSet::new()
+    };
+    // built-in components every state can use without registering them
+    world_state.register::<Transform>();
+    world_state.register::<GlobalTransform>();
+    world_state.register::<Name>();
+    world_state
+  }
+
+  fn shared() -> WorldState {
+    let mut world_state = WorldState::new();
+    world_state.first_generation = SHARED_GENERATION;
+    world_state
+  }
+
+  fn register<T: Component>(&mut self) {
+    self.components.insert(((), TypeId::of::<T>()), Box::new(ComponentStore::<T>::new()));
+  }
+}
+
+pub struct World {
+  world_state_stack: Vec<WorldState>,
+  // entities visible to every state through World::shared
+  shared_state: WorldState,
+  // resources that survive pushing, popping and switching states
+  global_resources: Resources,
+  commands: Commands,
+  change_tick: usize,
+  last_change_tick: usize,
+  // change tick at the previous clear_trackers; removal events older than it get dropped
+  frame_tick: usize,
+  prefabs: Rc<Prefabs>,
+  snapshot_types: SnapshotTypes,
+  events: EventQueues,
+  hooks: Hooks
+}
+
+// we don't want to expose these functions to the client with the rest of World's methods
+pub fn push_state(world: &mut World) {
+  push_state_with(world, StateInheritance::Empty);
+}
+
+pub fn pop_state(world: &mut World) {
+  try_pop_state(world).expect("Error: Attempted to pop empty ecs world state stack");
+}
+
+pub fn switch_state(world: &mut World) {
+  pop_state(world);
+  push_state(world);
+}
+
+// the popped state's entities are removed first, so their removal hooks run
+pub fn try_pop_state(world: &mut World) -> Result<(), EcsError> {
+  world.clear_state()?;
+  world.world_state_stack.pop().map(|_| ()).ok_or(EcsError::EmptyStateStack)
+}
+
+pub fn try_switch_state(world: &mut World) -> Result<(), EcsError> {
+  try_pop_state(world)?;
+  push_state(world);
+  Ok(())
+}
+
+fn find_resource<'a, R: Resource>(local: &'a Resources, global: &'a Resources) -> Option<&'a R> {
+  local.get(&TypeId::of::<R>())
+    .or_else(|| global.get(&TypeId::of::<R>()))
+    .and_then(|res| res.downcast_ref::<R>())
+}
+
+impl World {
+  pub fn new() -> World {
+    World {
+      world_state_stack: vec![WorldState::new()],
+      shared_state: WorldState::shared(),
+      global_resources: HashMap::new(),
+      commands: Commands::new(),
+      change_tick: 1,
+      last_change_tick: 0,
+      frame_tick: 0,
+      prefabs: Rc::new(Prefabs::new()),
+      snapshot_types: SnapshotTypes::new(),
+      events: HashMap::new(),
+      hooks: Hooks::new()
+    }
+  }
+
+  fn current_state(&self) -> &WorldState {
+    self.try_current_state().expect("Error: Could not find ecs world state")
+  }
+
+  fn current_state_mut(&mut self) -> &mut WorldState {
+    self.try_current_state_mut().expect("Error: Could not find ecs world state (mut)")
+  }
+
+  fn try_current_state(&self) -> Result<&WorldState, EcsError> {
+    self.world_state_stack.last().ok_or(EcsError::EmptyStateStack)
+  }
+
+  fn try_current_state_mut(&mut self) -> Result<&mut WorldState, EcsError> {
+    self.world_state_stack.last_mut().ok_or(EcsError::EmptyStateStack)
+  }
+
+  pub fn create(&mut self) -> Entity {
+    self.try_create().expect("Error: Could not find ecs world state (mut)")
+  }
+
+  pub fn try_create(&mut self) -> Result<Entity, EcsError> {
+    let entity = self.allocate()?;
+    self.run_entity_created(entity);
+    Ok(entity)
+  }
+
+  fn allocate(&mut self) -> Result<Entity, EcsError> {
+    let mut world_state = self.try_current_state_mut()?;
+    if world_state.current_id <= usize::MAX {
+      let new_id = match world_state.reusable_ids.pop() {
+        Some(reused_id) => reused_id,
+        None => {
+          let current_id = world_state.current_id;
+          world_state.current_id += 1;
+          let first_generation = world_state.first_generation;
+          world_state.generations.push(first_generation);
+          current_id
+        }
+      };
+      let entity = Entity(new_id, world_state.generations[new_id]);
+      world_state.active.insert(entity);
+      Ok(entity)
+    } else {
+      panic!("Error: Exceeded maximum entity limit")
+    }
+  }
+
+  // hands back ids from allocate that nothing else has seen yet, so their generation stays the same
+  fn release(&mut self, entities: &[Entity]) {
+    if let Ok(world_state) = self.try_current_state_mut() {
+      for e in entities.iter().rev() {
+        world_state.active.remove(e);
+        world_state.reusable_ids.push(e.0);
+      }
+    }
+  }
+
+  fn store<T: Component>(&self) -> Option<&ComponentStore<T>> {
+    let world_state = self.current_state();
+    world_state.components.get(&((), TypeId::of::<T>()))
+      .and_then(|store| store.downcast_ref::<ComponentStore<T>>())
+  }
+
+  fn store_mut<T: Component>(&mut self) -> Option<&mut ComponentStore<T>> {
+    let world_state = self.current_state_mut();
+    world_state.components.get_mut(&((), TypeId::of::<T>()))
+      .and_then(|store| store.downcast_mut::<ComponentStore<T>>())
+  }
+
+  fn try_store<T: Component>(&self) -> Result<&ComponentStore<T>, EcsError> {
+    let world_state = self.try_current_state()?;
+    world_state.components.get(&((), TypeId::of::<T>()))
+      .and_then(|store| store.downcast_ref::<ComponentStore<T>>())
+      .ok_or(EcsError::UnregisteredComponent(any::type_name::<T>()))
+  }
+
+  fn try_store_mut<T: Component>(&mut self) -> Result<&mut ComponentStore<T>, EcsError> {
+    let world_state = self.try_current_state_mut()?;
+    world_state.components.get_mut(&((), TypeId::of::<T>()))
+      .and_then(|store| store.downcast_mut::<ComponentStore<T>>())
+      .ok_or(EcsError::UnregisteredComponent(any::type_name::<T>()))
+  }
+
+  fn check_alive(&self, e: Entity) -> Result<(), EcsError> {
+    if self.try_current_state()?.active.contains(&e) {
+      Ok(())
+    } else {
+      Err(EcsError::DeadEntity(e))
+    }
+  }
+
+  pub fn register_comp<T: Component>(&mut self) {
+    self.try_register_comp::<T>().expect("Error: Could not find ecs world state (mut)")
+  }
+
+  pub fn try_register_comp<T: Component>(&mut self) -> Result<(), EcsError> {
+    self.try_current_state_mut()?.register::<T>();
+    Ok(())
+  }
+
+  fn ensure_registered<T: Component>(&mut self) {
+    if self.store::<T>().is_none() {
+      self.register_comp::<T>();
+    }
+  }
+
+  pub fn spawn(&mut self) -> EntityBuilder {
+    EntityBuilder::new(self)
+  }
+
+  pub fn spawn_bundle<B: Bundle>(&mut self, bundle: B) -> Entity {
+    let entity = self.create();
+    bundle.add_to(self, entity);
+    entity
+  }
+
+  pub fn add_comp<T: Component>(&mut self, e: Entity, comp: T) {
+    match self.try_add_comp(e, comp) {
+      Ok(()) => (),
+      Err(EcsError::DeadEntity(_)) =>
+        panic!("Error: Could not add component to entity; Entity is not alive"),
+      Err(EcsError::EmptyStateStack) =>
+        panic!("Error: Could not find ecs world state"),
+      Err(_) =>
+        panic!("Error: Could not add component to entity; Could not find corresponding registered component type")
+    }
+  }
+
+  pub fn try_add_comp<T: Component>(&mut self, e: Entity, comp: T) -> Result<(), EcsError> {
+    self.check_alive(e)?;
+    let tick = self.change_tick;
+    let old = self.try_store_mut::<T>()?.insert(e, comp, tick);
+    if let Some(old) = old {
+      self.run_on_remove(e, &old);
+    }
+    self.run_on_add::<T>(e);
+    Ok(())
+  }
+
+  pub fn get_comp<T: Component>(&self) -> StorageIter<T, T::Storage> {
+    self.try_get_comp::<T>().expect("Error: Could not find component of given type to retrieve")
+  }
+
+  pub fn try_get_comp<T: Component>(&self) -> Result<StorageIter<T, T::Storage>, EcsError> {
+    self.try_store::<T>().map(|typed_store| typed_store.iter())
+  }
+
+  pub fn get_comp_mut<T: Component>(&mut self) -> StorageIterMut<T, T::Storage> {
+    assert_writable::<T>();
+    self.try_get_comp_mut::<T>().expect("Error: Could not find component of given type to retrieve (mut)")
+  }
+
+  pub fn try_get_comp_mut<T: Component>(&mut self) -> Result<StorageIterMut<T, T::Storage>, EcsError> {
+    check_writable::<T>()?;
+    let tick = self.change_tick;
+    self.try_store_mut::<T>().map(|typed_store| typed_store.iter_mut(tick))
+  }
+
+  pub fn get<T: Component>(&self, e: Entity) -> Option<&T> {
+    if !self.contains(e) {
+      return None;
+    }
+    self.store::<T>().and_then(|typed_store| typed_store.get(e))
+  }
+
+  pub fn try_get<T: Component>(&self, e: Entity) -> Result<&T, EcsError> {
+    self.check_alive(e)?;
+    self.try_store::<T>()?.get(e).ok_or(EcsError::MissingComponent(e, any::type_name::<T>()))
+  }
+
+  pub fn get_mut<T: Component>(&mut self, e: Entity) -> Option<&mut T> {
+    assert_writable::<T>();
+    if !self.contains(e) {
+      return None;
+    }
+    let tick = self.change_tick;
+    self.store_mut::<T>().and_then(|typed_store| typed_store.get_mut(e, tick))
+  }
+
+  pub fn try_get_mut<T: Component>(&mut self, e: Entity) -> Result<&mut T, EcsError> {
+    check_writable::<T>()?;
+    self.check_alive(e)?;
+    let tick = self.change_tick;
+    self.try_store_mut::<T>()?.get_mut(e, tick).ok_or(EcsError::MissingComponent(e, any::type_name::<T>()))
+  }
+
+  pub fn has<T: Component>(&self, e: Entity) -> bool {
+    self.contains(e) && self.store::<T>().map_or(false, |typed_store| typed_store.contains(e))
+  }
+
+  pub fn remove_comp<T: Component>(&mut self, e: Entity) -> Option<T> {
+    if !self.contains(e) {
+      return None;
+    }
+    let tick = self.change_tick;
+    let comp = self.store_mut::<T>().and_then(|typed_store| typed_store.remove(e, tick));
+    if let Some(ref comp) = comp {
+      self.run_on_remove(e, comp);
+    }
+    comp
+  }
+
+  pub fn try_remove_comp<T: Component>(&mut self, e: Entity) -> Result<T, EcsError> {
+    self.check_alive(e)?;
+    let tick = self.change_tick;
+    let comp = self.try_store_mut::<T>()?.remove(e, tick).ok_or(EcsError::MissingComponent(e, any::type_name::<T>()))?;
+    self.run_on_remove(e, &comp);
+    Ok(comp)
+  }
+
+  pub fn par_for_each<T: Component, F: Fn(Entity, &T) + Sync>(&self, f: F) {
+    let comps: Vec<_> = self.get_comp::<T>().collect();
+    comps.into_par_iter().for_each(|(&e, comp)| f(e, comp));
+  }
+
+  pub fn par_for_each_mut<T: Component, F: Fn(Entity, &mut T) + Sync>(&mut self, f: F) {
+    let comps: Vec<_> = self.get_comp_mut::<T>().collect();
+    comps.into_par_iter().for_each(|(&e, comp)| f(e, comp));
+  }
+
+  pub fn query<'a, Q: Query<'a>>(&'a mut self) -> QueryIter<'a, Q> {
+    self.try_query::<Q>().unwrap_or_else(|err| panic!("Error: Could not find component of given type to query: {}", err))
+  }
+
+  pub fn try_query<'a, Q: Query<'a>>(&'a mut self) -> Result<QueryIter<'a, Q>, EcsError> {
+    let (reads, writes) = query::query_access::<Q>();
+    let ticks = self.change_ticks();
+    let world_state = self.try_current_state_mut()?;
+    let stores = query::store_ptrs(&mut world_state.components, &reads, &writes);
+    unsafe { QueryIter::new(&world_state.active, &stores, ticks) }
+  }
+
+  pub fn contains(&self, e: Entity) -> bool {
+    let world_state = self.current_state();
+    world_state.active.contains(&e)
+  }
+
+  pub fn remove(&mut self, e: Entity) {
+    match self.try_remove(e) {
+      Ok(()) | Err(EcsError::DeadEntity(_)) => (),
+      Err(_) => panic!("Error: Could not find ecs world state (mut)")
+    }
+  }
+
+  // also removes every descendant in the hierarchy
+  pub fn try_remove(&mut self, e: Entity) -> Result<(), EcsError> {
+    self.try_remove_recursive(e).map(|_| ())
+  }
+
+  // e stops counting as alive before any hooks run, but its id is only recycled once they're done
+  fn despawn(&mut self, e: Entity) -> Result<(), EcsError> {
+    if !self.try_current_state_mut()?.active.remove(&e) {
+      return Ok(());
+    }
+    self.take_hooked(e);
+    let tick = self.change_tick;
+    {
+      let world_state = self.try_current_state_mut()?;
+      for comp_store in world_state.components.values_mut() {
+        comp_store.store_remove(e, tick);
+      }
+      let next = world_state.generations[e.0].wrapping_add(1);
+      world_state.generations[e.0] = world_state.generation_floors.remove(&e.0).map_or(next, |floor| floor.max(next));
+      world_state.reusable_ids.push(e.0);
+    }
+    self.run_entity_removed(e);
+    self.try_current_state_mut()?.inherited.remove(&e);
+    Ok(())
+  }
+
+  // despawns every entity of the current state in id order, before the state is dropped;
+  // entities spawned by the hooks meanwhile are dropped along with it without running any
+  fn clear_state(&mut self) -> Result<(), EcsError> {
+    let mut entities: Vec<Entity> = self.try_current_state()?.active.iter().cloned().collect();
+    entities.sort();
+    for e in entities {
+      self.despawn(e)?;
+    }
+    Ok(())
+  }
+
+  pub fn change_ticks(&self) -> ChangeTicks {
+    ChangeTicks {
+      change_tick: self.change_tick,
+      last_change_tick: self.last_change_tick
+    }
+  }
+
+  // marks the end of a frame: everything up to now stops counting as added, changed or removed
+  pub fn clear_trackers(&mut self) {
+    let oldest = self.frame_tick;
+    for world_state in self.world_state_stack.iter_mut().chain(Some(&mut self.shared_state)) {
+      for comp_store in world_state.components.values_mut() {
+        comp_store.prune_removed(oldest);
+      }
+    }
+    self.frame_tick = self.change_tick;
+    self.last_change_tick = self.change_tick;
+    self.change_tick += 1;
+  }
+
+  // used by dispatchers so each system sees what changed since its own last run;
+  // returns the reader's previous last_change_tick for end_system
+  fn begin_system(&mut self, last_run: usize) -> usize {
+    self.change_tick += 1;
+    let previous = self.last_change_tick;
+    self.last_change_tick = last_run;
+    previous
+  }
+
+  // returns the tick the system should remember as its last run
+  fn end_system(&mut self, previous: usize) -> usize {
+    self.last_change_tick = previous;
+    self.change_tick
+  }
+
+  pub fn is_added<T: Component>(&self, e: Entity) -> bool {
+    self.contains(e) && self.store::<T>().map_or(false, |typed_store| typed_store.is_added(e, self.last_change_tick))
+  }
+
+  pub fn is_changed<T: Component>(&self, e: Entity) -> bool {
+    self.contains(e) && self.store::<T>().map_or(false, |typed_store| typed_store.is_changed(e, self.last_change_tick))
+  }
+
+  pub fn removed<T: Component>(&self) -> RemovedIter {
+    self.store::<T>()
+      .map(|typed_store| typed_store.removed(self.last_change_tick))
+      .expect("Error: Could not find component of given type to retrieve")
+  }
+
+  pub fn iter(&self) -> hash_set::Iter<Entity> {
+    let world_state = self.current_state();
+    world_state.active.iter()
+  }
+
+  // a handle to the world's command buffer, usable while the world itself is borrowed
+  pub fn commands(&self) -> Commands {
+    self.commands.clone()
+  }
+
+  pub fn maintain(&mut self) {
+    let commands = self.commands.clone();
+    commands.apply(self);
+  }
+
+  // dropped along with the current state when it is popped or switched out
+  pub fn insert_resource<R: Resource>(&mut self, res: R) {
+    let world_state = self.current_state_mut();
+    world_state.resources.insert(TypeId::of::<R>(), Box::new(res));
+  }
+
+  pub fn insert_global_resource<R: Resource>(&mut self, res: R) {
+    self.global_resources.insert(TypeId::of::<R>(), Box::new(res));
+  }
+
+  // the current state's resource shadows a global resource of the same type
+  pub fn resource<R: Resource>(&self) -> Option<&R> {
+    let world_state = self.current_state();
+    find_resource::<R>(&world_state.resources, &self.global_resources)
+  }
+
+  pub fn resource_mut<R: Resource>(&mut self) -> Option<&mut R> {
+    let World { ref mut world_state_stack, ref mut global_resources, .. } = *self;
+    let world_state = world_state_stack.last_mut().expect("Error: Could not find ecs world state (mut)");
+    match world_state.resources.get_mut(&TypeId::of::<R>()) {
+      Some(res) => res.downcast_mut::<R>(),
+      None => global_resources.get_mut(&TypeId::of::<R>()).and_then(|res| res.downcast_mut::<R>())
+    }
+  }
+
+  pub fn remove_resource<R: Resource>(&mut self) -> Option<R> {
+    let local = self.current_state_mut().resources.remove(&TypeId::of::<R>());
+    local.or_else(|| self.global_resources.remove(&TypeId::of::<R>()))
+      .and_then(|res| res.downcast::<R>().ok())
+      .map(|res| *res)
+  }
+}
+
+#[cfg(test)]
+mod ecs_tests {
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use super::*;
+
+  // describe: an ECS World
+
+  // it should allow a new Entity to be created
+  #[test]
+  fn test_create_entity() {
+    let mut test_world = World::new();
+    test_world.create();
+  }
+
+  // it should create entities with ascending ids
+  #[test]
+  fn test_entity_ids() {
+    let mut test_world = World::new();
+    let entity0 = test_world.create();
+    let entity1 = test_world.create();
+    assert_eq!(entity0.0, 0);
+    assert_eq!(entity1.0, 1);
+  }
+
+  struct TestComponent {
+    x: usize
+  }
+
+  impl Component for TestComponent {
+    type Storage = HashMapStorage<Self>;
+  }
+
+  // it should correctly add a component to an entity
+  #[test]
+  fn test_add_comp() {
+    let mut test_world = World::new();
+    let entity = test_world.create();
+    test_world.register_comp::<TestComponent>();
+    test_world.add_comp(entity, TestComponent{ x: 6 });
+    let test_comp = test_world.get_comp::<TestComponent>();
+    assert_eq!(test_comp.len(), 1);
+    for (e, comp) in test_comp {
+      assert_eq!(e.0, 0);
+      assert_eq!(comp.x, 6);
+    }
+  }
+
+  // it should allow an entity's components to be modified
+  #[test]
+  fn test_modify_comp() {
+    let mut test_world = World::new();
+    let entity = test_world.create();
+    test_world.register_comp::<TestComponent>();
+    test_world.add_comp(entity, TestComponent{ x: 6 });
+    {
+      let test_comp = test_world.get_comp_mut::<TestComponent>();
+      assert_eq!(test_comp.len(), 1);
+      for (e, comp) in test_comp {
+        assert_eq!(e.0, 0);
+        assert_eq!(comp.x, 6);
+        comp.x += 1;
+        assert_eq!(comp.x, 7);
+      }
+    }
+    let test_comp_again = test_world.get_comp::<TestComponent>();
+    assert_eq!(test_comp_again.len(), 1);
+    for (e, comp) in test_comp_again {
+      assert_eq!(e.0, 0);
+      assert_eq!(comp.x, 7);
+    }
+  }
+
+  // it should look up a single entity's component
+  #[test]
+  fn test_get() {
+    let mut test_world = World::new();
+    let entity0 = test_world.create();
+    let entity1 = test_world.create();
+    test_world.register_comp::<TestComponent>();
+    test_world.add_comp(entity0, TestComponent{ x: 6 });
+    test_world.add_comp(entity1, TestComponent{ x: 9 });
+    assert_eq!(test_world.get::<TestComponent>(entity0).map(|comp| comp.x), Some(6));
+    assert_eq!(test_world.get::<TestComponent>(entity1).map(|comp| comp.x), Some(9));
+    assert_eq!(test_world.has::<TestComponent>(entity1), true);
+  }
+
+  // it should allow a single entity's component to be modified
+  #[test]
+  fn test_get_mut() {
+    let mut test_world = World::new();
+    let entity = test_world.create();
+    test_world.register_comp::<TestComponent>();
+    test_world.add_comp(entity, TestComponent{ x: 6 });
+    if let Some(comp) = test_world.get_mut::<TestComponent>(entity) {
+      comp.x += 1;
+    }
+    assert_eq!(test_world.get::<TestComponent>(entity).map(|comp| comp.x), Some(7));
+  }
+
+  // it should return None when looking up a missing or unregistered component
+  #[test]
+  fn test_get_missing() {
+    let mut test_world = World::new();
+    let entity = test_world.create();
+    assert_eq!(test_world.get::<TestComponent>(entity).is_none(), true);
+    assert_eq!(test_world.has::<TestComponent>(entity), false);
+    test_world.register_comp::<TestComponent>();
+    assert_eq!(test_world.get_mut::<TestComponent>(entity).is_none(), true);
+    assert_eq!(test_world.has::<TestComponent>(entity), false);
+  }
+
+  // it should remove a single component from an entity and hand it back
+  #[test]
+  fn test_remove_comp() {
+    let mut test_world = World::new();
+    let entity = test_world.create();
+    test_world.register_comp::<TestComponent>();
+    test_world.add_comp(entity, TestComponent{ x: 6 });
+    assert_eq!(test_world.remove_comp::<TestComponent>(entity).map(|comp| comp.x), Some(6));
+    assert_eq!(test_world.has::<TestComponent>(entity), false);
+    assert_eq!(test_world.contains(entity), true);
+    assert_eq!(test_world.remove_comp::<TestComponent>(entity).is_none(), true);
+  }
+
+  // it should visit every component of a type in parallel
+  #[test]
+  fn test_par_for_each() {
+    let mut test_world = World::new();
+    test_world.register_comp::<TestComponent>();
+    for x in 0..100 {
+      let entity = test_world.create();
+      test_world.add_comp(entity, TestComponent{ x: x });
+    }
+    test_world.par_for_each_mut::<TestComponent, _>(|_, comp| comp.x *= 2);
+    let total = AtomicUsize::new(0);
+    test_world.par_for_each::<TestComponent, _>(|_, comp| { total.fetch_add(comp.x, Ordering::SeqCst); });
+    assert_eq!(total.load(Ordering::SeqCst), 9900);
+  }
+
+  // it should allow an entity to be removed
+  #[test]
+  fn test_remove() {
+    let mut test_world = World::new();
+    let entity = test_world.create();
+    assert_eq!(test_world.contains(entity), true);
+    test_world.remove(entity);
+    assert_eq!(test_world.contains(entity), false);
+  }
+
+  // it should reuse a removed entity's id with a new generation
+  #[test]
+  fn test_reuse_id() {
+    let mut test_world = World::new();
+    let entity0 = test_world.create();
+    test_world.remove(entity0);
+    let entity1 = test_world.create();
+    assert_eq!(entity1.id(), entity0.id());
+    assert_eq!(entity1.generation(), entity0.generation() + 1);
+    assert_eq!(test_world.contains(entity0), false);
+    assert_eq!(test_world.contains(entity1), true);
+    let entity2 = test_world.create();
+    assert_eq!(entity2.id(), 1);
+  }
+
+  // it should reject stale handles when accessing or removing components
+  #[test]
+  fn test_stale_handle() {
+    let mut test_world = World::new();
+    test_world.register_comp::<TestComponent>();
+    let stale = test_world.create();
+    test_world.remove(stale);
+    let entity = test_world.create();
+    test_world.add_comp(entity, TestComponent{ x: 6 });
+    assert_eq!(test_world.get::<TestComponent>(stale).is_none(), true);
+    assert_eq!(test_world.get_mut::<TestComponent>(stale).is_none(), true);
+    assert_eq!(test_world.has::<TestComponent>(stale), false);
+    assert_eq!(test_world.remove_comp::<TestComponent>(stale).is_none(), true);
+    test_world.remove(stale);
+    assert_eq!(test_world.contains(entity), true);
+    assert_eq!(test_world.get::<TestComponent>(entity).map(|comp| comp.x), Some(6));
+  }
+
+  // it should panic when trying to add a component to a removed entity
+  #[test]
+  #[should_panic(expected = "Error: Could not add component to entity; Entity is not alive")]
+  fn test_add_comp_stale_handle() {
+    let mut test_world = World::new();
+    test_world.register_comp::<TestComponent>();
+    let stale = test_world.create();
+    test_world.remove(stale);
+    test_world.create();
+    test_world.add_comp(stale, TestComponent{ x: 6 });
+  }
+
+  struct Score(usize);
+
+  struct DeltaTime(f64);
+
+  // it should store, mutate and remove resources
+  #[test]
+  fn test_resources() {
+    let mut test_world = World::new();
+    assert_eq!(test_world.resource::<Score>().is_none(), true);
+    test_world.insert_resource(Score(0));
+    if let Some(score) = test_world.resource_mut::<Score>() {
+      score.0 += 10;
+    }
+    assert_eq!(test_world.resource::<Score>().map(|score| score.0), Some(10));
+    assert_eq!(test_world.remove_resource::<Score>().map(|score| score.0), Some(10));
+    assert_eq!(test_world.resource::<Score>().is_none(), true);
+  }
+
+  // it should drop state resources on pop but keep global ones
+  #[test]
+  fn test_resource_lifetimes() {
+    let mut test_world = World::new();
+    test_world.insert_global_resource(DeltaTime(0.5));
+    push_state(&mut test_world);
+    test_world.insert_resource(Score(3));
+    assert_eq!(test_world.resource::<DeltaTime>().map(|dt| dt.0), Some(0.5));
+    pop_state(&mut test_world);
+    assert_eq!(test_world.resource::<Score>().is_none(), true);
+    switch_state(&mut test_world);
+    assert_eq!(test_world.resource::<DeltaTime>().map(|dt| dt.0), Some(0.5));
+  }
+
+  // it should prefer the current state's resource over a global one of the same type
+  #[test]
+  fn test_resource_shadowing() {
+    let mut test_world = World::new();
+    test_world.insert_global_resource(Score(1));
+    test_world.insert_resource(Score(2));
+    if let Some(score) = test_world.resource_mut::<Score>() {
+      score.0 += 10;
+    }
+    assert_eq!(test_world.resource::<Score>().map(|score| score.0), Some(12));
+    test_world.remove_resource::<Score>();
+    assert_eq!(test_world.resource::<Score>().map(|score| score.0), Some(1));
+  }
+
+  // it should properly handle pushing a new state on to the state stack
+  #[test]
+  fn test_push() {
+    let mut test_world = World::new();
+    let entity = test_world.create();
+    assert_eq!(test_world.contains(entity), true);
+    push_state(&mut test_world);
+    assert_eq!(test_world.contains(entity), false);
+  }
+
+  // it should properly handle popping a state off of the state stack
+  #[test]
+  fn test_pop() {
+    let mut test_world = World::new();
+    let entity = test_world.create();
+    assert_eq!(entity.0, 0);
+    test_world.register_comp::<TestComponent>();
+    test_world.add_comp(entity, TestComponent{ x: 6 });
+    {
+      let test_comp = test_world.get_comp::<TestComponent>();
+      assert_eq!(test_comp.len(), 1);
+      for (_, comp) in test_comp {
+        assert_eq!(comp.x, 6);
+      }
+    }
+    push_state(&mut test_world);
+    test_world.register_comp::<TestComponent>();
+    let entity_pushed = test_world.create();
+    assert_eq!(entity_pushed.0, 0);
+    test_world.add_comp(entity_pushed, TestComponent { x: 3 });
+    {
+      let test_comp_pushed = test_world.get_comp::<TestComponent>();
+      assert_eq!(test_comp_pushed.len(), 1);
+      for (_, comp) in test_comp_pushed {
+        assert_eq!(comp.x, 3);
+      }
+    }
+    pop_state(&mut test_world);
+    let test_comp_after_pop = test_world.get_comp::<TestComponent>();
+    assert_eq!(test_comp_after_pop.len(), 1);
+    for (_, comp) in test_comp_after_pop {
+      assert_eq!(comp.x, 6);
+    }
+  }
+
+  // it should properly handle swapping states on the state stack
+  #[test]
+  fn test_switch() {
+    let mut test_world = World::new();
+    let entity = test_world.create();
+    assert_eq!(entity.0, 0);
+    test_world.register_comp::<TestComponent>();
+    test_world.add_comp(entity, TestComponent{ x: 6 });
+    {
+      let test_comp = test_world.get_comp::<TestComponent>();
+      assert_eq!(test_comp.len(), 1);
+      for (_, comp) in test_comp {
+        assert_eq!(comp.x, 6);
+      }
+    }
+    push_state(&mut test_world);
+    test_world.register_comp::<TestComponent>();
+    let entity_pushed = test_world.create();
+    assert_eq!(entity_pushed.0, 0);
+    test_world.add_comp(entity_pushed, TestComponent { x: 3 });
+    {
+      let test_comp_pushed = test_world.get_comp::<TestComponent>();
+      assert_eq!(test_comp_pushed.len(), 1);
+      for (_, comp) in test_comp_pushed {
+        assert_eq!(comp.x, 3);
+      }
+    }
+    switch_state(&mut test_world);
+    assert_eq!(test_world.contains(entity_pushed), false);
+    pop_state(&mut test_world);
+    let test_comp_after_pop = test_world.get_comp::<TestComponent>();
+    assert_eq!(test_comp_after_pop.len(), 1);
+    for (_, comp) in test_comp_after_pop {
+      assert_eq!(comp.x, 6);
+    }
+  }
+
+  // it should panic when trying to pop an empty world state stack
+  #[test]
+  #[should_panic(expected = "Error: Attempted to pop empty ecs world state stack")]
+  fn test_pop_empty_world_state_stack() {
+    let mut test_world = World::new();
+    pop_state(&mut test_world);
+    pop_state(&mut test_world);
+  }
+
+  // it should panic when trying to switch states with an empty world state stack
+  #[test]
+  #[should_panic(expected = "Error: Attempted to pop empty ecs world state stack")]
+  fn test_switch_empty_state_stack() {
+    let mut test_world = World::new();
+    pop_state(&mut test_world);
+    switch_state(&mut test_world);
+  }
+
+  // it should panic when trying to get a reference to the current state from an empty state stack
+  #[test]
+  #[should_panic(expected = "Error: Could not find ecs world state")]
+  fn test_current_state_empty_state_stack() {
+    let mut test_world = World::new();
+    pop_state(&mut test_world);
+    test_world.current_state();
+  }
+
+  // it should panic when trying to get a mutable reference to the current state from an empty state stack
+  #[test]
+  #[should_panic(expected = "Error: Could not find ecs world state (mut)")]
+  fn test_current_mut_state_empty_state_stack() {
+    let mut test_world = World::new();
+    pop_state(&mut test_world);
+    test_world.current_state_mut();
+  }
+
+  // it should panic when trying to add a new component to an entity before registering the component
+  #[test]
+  #[should_panic(expected = "Error: Could not add component to entity; Could not find corresponding registered component type")]
+  fn test_add_comp_no_registration() {
+    let mut test_world = World::new();
+    let entity = test_world.create();
+    test_world.add_comp(entity, TestComponent{ x: 6 });
+  }
+
+  // it should panic when trying to get a reference to an unregistered component
+  #[test]
+  #[should_panic(expected = "Error: Could not find component of given type to retrieve")]
+  fn test_get_comp_no_registration() {
+    let mut test_world = World::new();
+    test_world.create();
+    test_world.get_comp::<TestComponent>();
+  }
+
+  // it should panic when trying to get a mutable reference to an unregistered component
+  #[test]
+  #[should_panic(expected = "Error: Could not find component of given type to retrieve (mut)")]
+  fn test_get_comp_mut_no_registration() {
+    let mut test_world = World::new();
+    test_world.create();
+    test_world.get_comp_mut::<TestComponent>();
+  }
+
+  // it should report removed components until the trackers are cleared
+  #[test]
+  fn test_removed_components() {
+    let mut test_world = World::new();
+    test_world.register_comp::<TestComponent>();
+    let entity0 = test_world.spawn_bundle((TestComponent{ x: 1 },));
+    let entity1 = test_world.spawn_bundle((TestComponent{ x: 2 },));
+    test_world.remove_comp::<TestComponent>(entity0);
+    test_world.remove(entity1);
+    assert_eq!(test_world.removed::<TestComponent>().collect::<Vec<_>>(), vec![entity0, entity1]);
+    test_world.clear_trackers();
+    assert_eq!(test_world.removed::<TestComponent>().count(), 0);
+  }
+
+  // it should track added and changed components per entity
+  #[test]
+  fn test_added_changed() {
+    let mut test_world = World::new();
+    let entity = test_world.spawn_bundle((TestComponent{ x: 1 },));
+    assert_eq!(test_world.is_added::<TestComponent>(entity), true);
+    test_world.clear_trackers();
+    assert_eq!(test_world.is_added::<TestComponent>(entity), false);
+    assert_eq!(test_world.is_changed::<TestComponent>(entity), false);
+    test_world.get_comp_mut::<TestComponent>();
+    assert_eq!(test_world.is_changed::<TestComponent>(entity), true);
+  }
+
+  // it should report failures as EcsErrors from the try_ variants
+  #[test]
+  fn test_try_errors() {
+    let mut test_world = World::new();
+    let entity = test_world.create();
+    assert_eq!(test_world.try_add_comp(entity, TestComponent{ x: 6 }).err(),
+      Some(EcsError::UnregisteredComponent(any::type_name::<TestComponent>())));
+    assert_eq!(test_world.try_get_comp::<TestComponent>().err(),
+      Some(EcsError::UnregisteredComponent(any::type_name::<TestComponent>())));
+    test_world.register_comp::<TestComponent>();
+    assert_eq!(test_world.try_get::<TestComponent>(entity).err(),
+      Some(EcsError::MissingComponent(entity, any::type_name::<TestComponent>())));
+    assert_eq!(test_world.try_add_comp(entity, TestComponent{ x: 6 }), Ok(()));
+    assert_eq!(test_world.try_get::<TestComponent>(entity).map(|comp| comp.x), Ok(6));
+    assert_eq!(test_world.try_remove(entity), Ok(()));
+    assert_eq!(test_world.try_remove(entity), Err(EcsError::DeadEntity(entity)));
+    assert_eq!(test_world.try_remove_comp::<TestComponent>(entity).err(), Some(EcsError::DeadEntity(entity)));
+  }
+
+  // it should report an empty world state stack instead of panicking from the try_ variants
+  #[test]
+  fn test_try_empty_state_stack() {
+    let mut test_world = World::new();
+    assert_eq!(try_pop_state(&mut test_world), Ok(()));
+    assert_eq!(try_pop_state(&mut test_world), Err(EcsError::EmptyStateStack));
+    assert_eq!(try_switch_state(&mut test_world), Err(EcsError::EmptyStateStack));
+    assert_eq!(test_world.try_create(), Err(EcsError::EmptyStateStack));
+    assert_eq!(test_world.try_register_comp::<TestComponent>(), Err(EcsError::EmptyStateStack));
+    assert_eq!(test_world.try_query::<&TestComponent>().err(), Some(EcsError::EmptyStateStack));
+  }
+
+  // it should return Unit when trying to remove an entity that is not active
+  #[test]
+  fn test_remove_inactive() {
+    let mut test_world = World::new();
+    assert_eq!(test_world.remove(Entity(50, 0)), ());
+  }
+
+}
\ No newline at end of file
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use yaml_rust::Yaml;
+
+use super::{Entity, Component, World, Serializable, EntityMap};
+use super::{Storage, StorageIter, HashMapStorage, NullStorage};
+
+// A human-readable label such as "player" or "boss_door". Names don't have to be unique.
+// Names are read-only so the index used by World::find_by_name always sees them change;
+// rename an entity with World::set_name.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Name(String);
+
+impl Name {
+  pub fn new<S: Into<String>>(name: S) -> Name {
+    Name(name.into())
+  }
+
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+}
+
+impl Component for Name {
+  type Storage = NameStorage;
+  const READ_ONLY: bool = true;
+}
+
+impl Serializable for Name {
+  fn serialize(&self) -> Yaml {
+    Yaml::String(self.0.clone())
+  }
+
+  fn deserialize(data: &Yaml, _entities: &EntityMap) -> Option<Name> {
+    data.as_str().map(Name::new)
+  }
+}
+
+// Keeps a name -> entities index next to the components so lookups don't scan every entity
+#[derive(Clone)]
+pub struct NameStorage {
+  names: HashMapStorage<Name>,
+  index: HashMap<String, Vec<Entity>>
+}
+
+impl NameStorage {
+  fn unindex(&mut self, e: Entity, name: &str) {
+    let now_empty = match self.index.get_mut(name) {
+      Some(entities) => {
+        entities.retain(|&named| named != e);
+        entities.is_empty()
+      },
+      None => false
+    };
+    if now_empty {
+      self.index.remove(name);
+    }
+  }
+
+  // in the order the names were given
+  fn find<'a>(&'a self, name: &'a str) -> Box<Iterator<Item = Entity> + 'a> {
+    let entities = self.index.get(name).map_or(&[][..], |entities| &entities[..]);
+    Box::new(entities.iter()
+      .cloned()
+      .filter(move |&e| self.names.get(e).map_or(false, |current| current.0 == name)))
+  }
+}
+
+impl Storage<Name> for NameStorage {
+  type Iter<'a> = <HashMapStorage<Name> as Storage<Name>>::Iter<'a>;
+  type IterMut<'a> = <HashMapStorage<Name> as Storage<Name>>::IterMut<'a>;
+
+  fn new() -> NameStorage {
+    NameStorage {
+      names: HashMapStorage::new(),
+      index: HashMap::new()
+    }
+  }
+
+  fn insert(&mut self, e: Entity, comp: Name) -> Option<Name> {
+    let key = comp.0.clone();
+    let old = self.names.insert(e, comp);
+    if let Some(ref old) = old {
+      self.unindex(e, &old.0);
+    }
+    self.index.entry(key).or_insert_with(Vec::new).push(e);
+    old
+  }
+
+  fn get(&self, e: Entity) -> Option<&Name> {
+    self.names.get(e)
+  }
+
+  fn get_mut(&mut self, e: Entity) -> Option<&mut Name> {
+    self.names.get_mut(e)
+  }
+
+  fn remove(&mut self, e: Entity) -> Option<Name> {
+    let old = self.names.remove(e);
+    if let Some(ref old) = old {
+      self.unindex(e, &old.0);
+    }
+    old
+  }
+
+  fn iter<'a>(&'a self) -> Self::Iter<'a> {
+    self.names.iter()
+  }
+
+  fn iter_mut<'a>(&'a mut self) -> Self::IterMut<'a> {
+    self.names.iter_mut()
+  }
+
+  fn entities(&self) -> &[Entity] {
+    self.names.entities()
+  }
+
+  unsafe fn get_ptr(this: *mut Self, e: Entity) -> Option<*mut Name> {
+    HashMapStorage::get_ptr(&mut (*this).names, e)
+  }
+
+  fn contains(&self, e: Entity) -> bool {
+    self.names.contains(e)
+  }
+}
+
+// Entities carrying the tag component T; empty if T was never registered
+pub struct TagIter<'a, T: Component + 'a> {
+  inner: Option<StorageIter<'a, T, NullStorage<T>>>
+}
+
+impl<'a, T: Component> Iterator for TagIter<'a, T> {
+  type Item = Entity;
+
+  fn next(&mut self) -> Option<Entity> {
+    self.inner.as_mut().and_then(|inner| inner.next()).map(|(&e, _)| e)
+  }
+}
+
+impl World {
+  pub fn set_name(&mut self, e: Entity, name: &str) {
+    self.add_comp(e, Name::new(name));
+  }
+
+  pub fn name(&self, e: Entity) -> Option<&str> {
+    self.get::<Name>(e).map(|name| name.as_str())
+  }
+
+  // the first entity given the name that is still alive
+  pub fn find_by_name(&self, name: &str) -> Option<Entity> {
+    self.find_all_by_name(name).into_iter().next()
+  }
+
+  pub fn find_all_by_name(&self, name: &str) -> Vec<Entity> {
+    match self.store::<Name>() {
+      Some(typed_store) => typed_store.data.find(name).filter(|&e| self.contains(e)).collect(),
+      None => Vec::new()
+    }
+  }
+
+  pub fn with_tag<T: Component<Storage = NullStorage<T>>>(&self) -> TagIter<T> {
+    TagIter { inner: self.store::<T>().map(|typed_store| typed_store.iter()) }
+  }
+}
+
+#[cfg(test)]
+mod name_tests {
+  use super::super::*;
+
+  // describe: entity names and tags
+
+  struct Enemy;
+
+  impl Component for Enemy {
+    type Storage = NullStorage<Self>;
+  }
+
+  // it should find entities by name
+  #[test]
+  fn test_find_by_name() {
+    let mut test_world = World::new();
+    let player = test_world.create();
+    let door = test_world.spawn_bundle((Name::new("boss_door"),));
+    test_world.set_name(player, "player");
+    assert_eq!(test_world.find_by_name("player"), Some(player));
+    assert_eq!(test_world.find_by_name("boss_door"), Some(door));
+    assert_eq!(test_world.find_by_name("goblin"), None);
+    assert_eq!(test_world.name(player), Some("player"));
+  }
+
+  // it should keep the index up to date through renames and removals
+  #[test]
+  fn test_index_maintenance() {
+    let mut test_world = World::new();
+    let first = test_world.create();
+    let second = test_world.create();
+    test_world.set_name(first, "guard");
+    test_world.set_name(second, "guard");
+    assert_eq!(test_world.find_all_by_name("guard"), vec![first, second]);
+    test_world.set_name(first, "captain");
+    assert_eq!(test_world.find_by_name("guard"), Some(second));
+    assert_eq!(test_world.find_by_name("captain"), Some(first));
+    test_world.remove(second);
+    assert_eq!(test_world.find_by_name("guard"), None);
+    test_world.remove_comp::<Name>(first);
+    assert_eq!(test_world.find_by_name("captain"), None);
+  }
+
+  // it should only let names change through set_name
+  #[test]
+  fn test_read_only() {
+    let mut test_world = World::new();
+    let guard = test_world.spawn_bundle((Name::new("guard"),));
+    assert_eq!(test_world.try_get_mut::<Name>(guard).err(), Some(EcsError::ReadOnlyComponent(any::type_name::<Name>())));
+    assert!(test_world.try_get_comp_mut::<Name>().is_err());
+    assert_eq!(test_world.find_by_name("guard"), Some(guard));
+  }
+
+  #[test]
+  #[should_panic(expected = "Error: Could not borrow component mutably")]
+  fn test_query_read_only() {
+    let mut test_world = World::new();
+    test_world.query::<&mut Name>();
+  }
+
+  // it should iterate over the entities with a tag
+  #[test]
+  fn test_with_tag() {
+    let mut test_world = World::new();
+    assert_eq!(test_world.with_tag::<Enemy>().count(), 0);
+    let goblin = test_world.spawn_bundle((Enemy,));
+    test_world.create();
+    let orc = test_world.spawn_bundle((Enemy, Name::new("orc")));
+    let mut enemies: Vec<Entity> = test_world.with_tag::<Enemy>().collect();
+    enemies.sort();
+    assert_eq!(enemies, vec![goblin, orc]);
+  }
+}
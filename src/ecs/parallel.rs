@@ -0,0 +1,437 @@
+use std::any::TypeId;
+use std::collections::HashSet;
+use std::collections::hash_set;
+use rayon;
+
+use super::{Entity, Component, ComponentStore, World, System, StorageIter, StorageIterMut, Resource, Resources, Commands};
+use super::{ChangeTicks, RemovedIter, Event, EventReader, EventIter, assert_writable};
+use super::events::{self, EventQueues};
+use super::query::{self, Query, QueryIter, StorePtrs};
+
+// A system that only touches the component types it declares, so it can share a frame with others
+pub trait ParSystem: Send {
+  fn reads(&self) -> Vec<TypeId> {
+    Vec::new()
+  }
+
+  fn writes(&self) -> Vec<TypeId> {
+    Vec::new()
+  }
+
+  fn run(&mut self, data: &mut SystemData);
+}
+
+// A ParSystem's view of the world, limited to the component types it declared
+pub struct SystemData<'a> {
+  active: &'a HashSet<Entity>,
+  resources: &'a Resources,
+  global_resources: &'a Resources,
+  commands: &'a Commands,
+  events: &'a EventQueues,
+  ticks: ChangeTicks,
+  stores: StorePtrs,
+  reads: Vec<TypeId>,
+  writes: Vec<TypeId>
+}
+
+// the dispatcher never hands two SystemData with conflicting access to different threads at once
+unsafe impl<'a> Send for SystemData<'a> {}
+
+impl<'a> SystemData<'a> {
+  fn check_read(&self, ty: TypeId) {
+    if !self.reads.contains(&ty) && !self.writes.contains(&ty) {
+      panic!("Error: System read a component type it did not declare")
+    }
+  }
+
+  fn check_write(&self, ty: TypeId) {
+    if !self.writes.contains(&ty) {
+      panic!("Error: System wrote to a component type it did not declare")
+    }
+  }
+
+  fn store<T: Component>(&self) -> Option<&ComponentStore<T>> {
+    self.check_read(TypeId::of::<T>());
+    unsafe { query::fetch_store::<T>(&self.stores).map(|typed_store| &*typed_store) }
+  }
+
+  fn store_mut<T: Component>(&mut self) -> Option<&mut ComponentStore<T>> {
+    assert_writable::<T>();
+    self.check_write(TypeId::of::<T>());
+    unsafe { query::fetch_store_mut::<T>(&self.stores).map(|typed_store| &mut *typed_store) }
+  }
+
+  pub fn get_comp<T: Component>(&self) -> StorageIter<T, T::Storage> {
+    self.store::<T>()
+      .map(|typed_store| typed_store.iter())
+      .expect("Error: Could not find component of given type to retrieve")
+  }
+
+  pub fn get_comp_mut<T: Component>(&mut self) -> StorageIterMut<T, T::Storage> {
+    let tick = self.ticks.change_tick;
+    self.store_mut::<T>()
+      .map(|typed_store| typed_store.iter_mut(tick))
+      .expect("Error: Could not find component of given type to retrieve (mut)")
+  }
+
+  pub fn get<T: Component>(&self, e: Entity) -> Option<&T> {
+    if !self.contains(e) {
+      return None;
+    }
+    self.store::<T>().and_then(|typed_store| typed_store.get(e))
+  }
+
+  pub fn get_mut<T: Component>(&mut self, e: Entity) -> Option<&mut T> {
+    if !self.contains(e) {
+      return None;
+    }
+    let tick = self.ticks.change_tick;
+    self.store_mut::<T>().and_then(|typed_store| typed_store.get_mut(e, tick))
+  }
+
+  pub fn query<'b, Q: Query<'b>>(&'b mut self) -> QueryIter<'b, Q> {
+    let (reads, writes) = query::query_access::<Q>();
+    for &ty in &reads {
+      self.check_read(ty);
+    }
+    for &ty in &writes {
+      self.check_write(ty);
+    }
+    unsafe { QueryIter::new(self.active, &self.stores, self.ticks) }
+      .unwrap_or_else(|err| panic!("Error: Could not find component of given type to query: {}", err))
+  }
+
+  pub fn is_added<T: Component>(&self, e: Entity) -> bool {
+    self.contains(e) && self.store::<T>().map_or(false, |typed_store| typed_store.is_added(e, self.ticks.last_change_tick))
+  }
+
+  pub fn is_changed<T: Component>(&self, e: Entity) -> bool {
+    self.contains(e) && self.store::<T>().map_or(false, |typed_store| typed_store.is_changed(e, self.ticks.last_change_tick))
+  }
+
+  pub fn removed<T: Component>(&self) -> RemovedIter {
+    self.store::<T>()
+      .map(|typed_store| typed_store.removed(self.ticks.last_change_tick))
+      .expect("Error: Could not find component of given type to retrieve")
+  }
+
+  // resources are shared read-only between parallel systems
+  pub fn resource<R: Resource>(&self) -> Option<&R> {
+    super::find_resource::<R>(self.resources, self.global_resources)
+  }
+
+  // sending has to go through commands().send so systems in the same batch don't race
+  pub fn read_events<E: Event>(&self, reader: &mut EventReader<E>) -> EventIter<E> {
+    events::read_events(self.events, reader)
+  }
+
+  // structural changes have to wait until the world is maintained
+  pub fn commands(&self) -> Commands {
+    self.commands.clone()
+  }
+
+  pub fn contains(&self, e: Entity) -> bool {
+    self.active.contains(&e)
+  }
+
+  pub fn iter(&self) -> hash_set::Iter<Entity> {
+    self.active.iter()
+  }
+}
+
+fn conflicts(a: &ParSystem, b: &ParSystem) -> bool {
+  let (a_reads, a_writes) = (a.reads(), a.writes());
+  let (b_reads, b_writes) = (b.reads(), b.writes());
+  a_writes.iter().any(|ty| b_reads.contains(ty) || b_writes.contains(ty)) ||
+    b_writes.iter().any(|ty| a_reads.contains(ty))
+}
+
+// Runs ParSystems on the rayon thread pool. Systems are split into batches that run one after
+// another; a system goes in the batch after the last earlier system it conflicts with.
+// A ParDispatcher is itself a System, so it can be added to a Dispatcher as one step of the frame.
+pub struct ParDispatcher {
+  systems: Vec<Box<ParSystem>>,
+  batches: Vec<usize>,
+  last_runs: Vec<usize>
+}
+
+impl ParDispatcher {
+  pub fn new() -> ParDispatcher {
+    ParDispatcher {
+      systems: Vec::new(),
+      batches: Vec::new(),
+      last_runs: Vec::new()
+    }
+  }
+
+  pub fn add_system<S: ParSystem + 'static>(&mut self, system: S) {
+    let batch = self.systems.iter().zip(self.batches.iter())
+      .filter(|&(other, _)| conflicts(&**other, &system))
+      .map(|(_, &batch)| batch + 1)
+      .max()
+      .unwrap_or(0);
+    self.systems.push(Box::new(system));
+    self.batches.push(batch);
+    self.last_runs.push(0);
+  }
+
+  pub fn run(&mut self, world: &mut World) {
+    let batch_count = self.batches.iter().map(|&batch| batch + 1).max().unwrap_or(0);
+    let World { ref mut world_state_stack, ref global_resources, ref commands, ref events, ref mut change_tick, .. } = *world;
+    let world_state = world_state_stack.last_mut().expect("Error: Could not find ecs world state (mut)");
+    for current_batch in 0..batch_count {
+      *change_tick += 1;
+      let batch: Vec<_> = self.systems.iter_mut().zip(self.batches.iter()).zip(self.last_runs.iter_mut())
+        .filter(|&((_, &batch), _)| batch == current_batch)
+        .map(|((system, _), last_run)| (system, last_run))
+        .collect();
+      let accesses: Vec<_> = batch.iter().map(|&(ref system, _)| (system.reads(), system.writes())).collect();
+      let batch_reads: Vec<TypeId> = accesses.iter().flat_map(|&(ref reads, _)| reads.iter().cloned()).collect();
+      let batch_writes: Vec<TypeId> = accesses.iter().flat_map(|&(_, ref writes)| writes.iter().cloned()).collect();
+      // looked up once for the whole batch; only the one system writing a type gets it mutably
+      let stores = query::store_ptrs(&mut world_state.components, &batch_reads, &batch_writes);
+      let mut jobs = Vec::new();
+      for ((system, last_run), (reads, writes)) in batch.into_iter().zip(accesses) {
+        let data = SystemData {
+          active: &world_state.active,
+          resources: &world_state.resources,
+          global_resources: global_resources,
+          commands: commands,
+          events: events,
+          ticks: ChangeTicks {
+            change_tick: *change_tick,
+            last_change_tick: *last_run
+          },
+          stores: stores.clone(),
+          reads: reads,
+          writes: writes
+        };
+        *last_run = *change_tick;
+        jobs.push((system, data));
+      }
+      rayon::scope(|scope| {
+        for (system, mut data) in jobs {
+          scope.spawn(move |_| system.run(&mut data));
+        }
+      });
+    }
+  }
+}
+
+impl System for ParDispatcher {
+  fn reads(&self) -> Vec<TypeId> {
+    self.systems.iter().flat_map(|system| system.reads()).collect()
+  }
+
+  fn writes(&self) -> Vec<TypeId> {
+    self.systems.iter().flat_map(|system| system.writes()).collect()
+  }
+
+  fn run(&mut self, world: &mut World) {
+    ParDispatcher::run(self, world);
+  }
+}
+
+#[cfg(test)]
+mod parallel_tests {
+  use std::any::TypeId;
+  use std::sync::Arc;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use super::super::*;
+
+  // describe: a parallel system dispatcher
+
+  struct Position {
+    x: usize
+  }
+
+  impl Component for Position {
+    type Storage = VecStorage<Self>;
+  }
+
+  struct Velocity {
+    dx: usize
+  }
+
+  impl Component for Velocity {
+    type Storage = VecStorage<Self>;
+  }
+
+  struct Health {
+    hp: usize
+  }
+
+  impl Component for Health {
+    type Storage = HashMapStorage<Self>;
+  }
+
+  struct Movement;
+
+  impl ParSystem for Movement {
+    fn reads(&self) -> Vec<TypeId> {
+      vec![TypeId::of::<Velocity>()]
+    }
+
+    fn writes(&self) -> Vec<TypeId> {
+      vec![TypeId::of::<Position>()]
+    }
+
+    fn run(&mut self, data: &mut SystemData) {
+      for (_, (pos, vel)) in data.query::<(&mut Position, &Velocity)>() {
+        pos.x += vel.dx;
+      }
+    }
+  }
+
+  struct Regen;
+
+  impl ParSystem for Regen {
+    fn writes(&self) -> Vec<TypeId> {
+      vec![TypeId::of::<Health>()]
+    }
+
+    fn run(&mut self, data: &mut SystemData) {
+      for (_, health) in data.get_comp_mut::<Health>() {
+        health.hp += 1;
+      }
+    }
+  }
+
+  struct Accelerate;
+
+  impl ParSystem for Accelerate {
+    fn writes(&self) -> Vec<TypeId> {
+      vec![TypeId::of::<Velocity>()]
+    }
+
+    fn run(&mut self, data: &mut SystemData) {
+      for (_, vel) in data.get_comp_mut::<Velocity>() {
+        vel.dx *= 10;
+      }
+    }
+  }
+
+  struct Gravity(usize);
+
+  struct Fall;
+
+  impl ParSystem for Fall {
+    fn writes(&self) -> Vec<TypeId> {
+      vec![TypeId::of::<Velocity>()]
+    }
+
+    fn run(&mut self, data: &mut SystemData) {
+      let gravity = data.resource::<Gravity>().map_or(0, |gravity| gravity.0);
+      for (_, vel) in data.get_comp_mut::<Velocity>() {
+        vel.dx += gravity;
+      }
+    }
+  }
+
+  // adds up the positions it reads into a total shared with the test
+  struct Survey(Arc<AtomicUsize>);
+
+  impl ParSystem for Survey {
+    fn reads(&self) -> Vec<TypeId> {
+      vec![TypeId::of::<Position>()]
+    }
+
+    fn run(&mut self, data: &mut SystemData) {
+      for (_, pos) in data.query::<&Position>() {
+        self.0.fetch_add(pos.x, Ordering::SeqCst);
+      }
+    }
+  }
+
+  struct Sneaky;
+
+  impl ParSystem for Sneaky {
+    fn run(&mut self, data: &mut SystemData) {
+      data.get_comp::<Health>();
+    }
+  }
+
+  fn setup_world() -> (World, Entity) {
+    let mut test_world = World::new();
+    test_world.register_comp::<Position>();
+    test_world.register_comp::<Velocity>();
+    test_world.register_comp::<Health>();
+    let entity = test_world.create();
+    test_world.add_comp(entity, Position{ x: 1 });
+    test_world.add_comp(entity, Velocity{ dx: 2 });
+    test_world.add_comp(entity, Health{ hp: 5 });
+    (test_world, entity)
+  }
+
+  // it should batch systems that don't conflict together and keep conflicting ones in order
+  #[test]
+  fn test_batches() {
+    let mut dispatcher = ParDispatcher::new();
+    dispatcher.add_system(Movement);
+    dispatcher.add_system(Regen);
+    dispatcher.add_system(Accelerate);
+    assert_eq!(dispatcher.batches, vec![0, 0, 1]);
+  }
+
+  // it should run every system against the current world
+  #[test]
+  fn test_run() {
+    let (mut test_world, entity) = setup_world();
+    let mut dispatcher = ParDispatcher::new();
+    dispatcher.add_system(Movement);
+    dispatcher.add_system(Regen);
+    dispatcher.add_system(Accelerate);
+    dispatcher.run(&mut test_world);
+    assert_eq!(test_world.get::<Position>(entity).map(|pos| pos.x), Some(3));
+    assert_eq!(test_world.get::<Velocity>(entity).map(|vel| vel.dx), Some(20));
+    assert_eq!(test_world.get::<Health>(entity).map(|health| health.hp), Some(6));
+  }
+
+  // it should run as a single step of a sequential dispatcher
+  #[test]
+  fn test_nested() {
+    let (mut test_world, entity) = setup_world();
+    let mut par_dispatcher = ParDispatcher::new();
+    par_dispatcher.add_system(Movement);
+    par_dispatcher.add_system(Regen);
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add_system("parallel", par_dispatcher);
+    dispatcher.run(&mut test_world);
+    assert_eq!(test_world.get::<Position>(entity).map(|pos| pos.x), Some(3));
+  }
+
+  // it should let systems read resources
+  #[test]
+  fn test_resources() {
+    let (mut test_world, entity) = setup_world();
+    test_world.insert_global_resource(Gravity(4));
+    let mut dispatcher = ParDispatcher::new();
+    dispatcher.add_system(Fall);
+    dispatcher.run(&mut test_world);
+    assert_eq!(test_world.get::<Velocity>(entity).map(|vel| vel.dx), Some(6));
+  }
+
+  // it should let systems in the same batch read the same component type at once
+  #[test]
+  fn test_shared_reads() {
+    let (mut test_world, _) = setup_world();
+    let total = Arc::new(AtomicUsize::new(0));
+    let mut dispatcher = ParDispatcher::new();
+    dispatcher.add_system(Survey(total.clone()));
+    dispatcher.add_system(Survey(total.clone()));
+    dispatcher.add_system(Regen);
+    assert_eq!(dispatcher.batches, vec![0, 0, 0]);
+    dispatcher.run(&mut test_world);
+    assert_eq!(total.load(Ordering::SeqCst), 2);
+  }
+
+  // it should panic when a system touches a component type it did not declare
+  #[test]
+  #[should_panic(expected = "Error: System read a component type it did not declare")]
+  fn test_undeclared_access() {
+    let (mut test_world, _) = setup_world();
+    let mut dispatcher = ParDispatcher::new();
+    dispatcher.add_system(Sneaky);
+    dispatcher.run(&mut test_world);
+  }
+}
@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use yaml_rust::{Yaml, YamlLoader};
+use yaml_rust::yaml::Hash;
+
+use config_loader;
+use super::{Entity, Component, World, Serializable, WorldSerializer, EntityMap, SerializeError};
+
+// Entity templates read from YAML, keyed by prefab name:
+//
+//   goblin:
+//     extends: enemy
+//     components:
+//       Health: 10
+//       Transform: { translation: [4.0, 0.0] }
+//     children:
+//       - extends: sword
+//       - components:
+//           Transform: { translation: [0.0, -8.0] }
+//
+// Components are looked up by the names they were registered under. A prefab that extends
+// another starts from the base's components, with its own fields merged over them, and
+// spawns the base's children before its own.
+pub struct Prefabs {
+  components: WorldSerializer,
+  definitions: HashMap<String, Yaml>
+}
+
+struct Resolved {
+  components: Hash,
+  children: Vec<Yaml>
+}
+
+fn merge(base: &Yaml, over: &Yaml) -> Yaml {
+  match (base, over) {
+    (&Yaml::Hash(ref base), &Yaml::Hash(ref over)) => {
+      let mut merged = base.clone();
+      for (key, value) in over {
+        let value = match base.get(key) {
+          Some(base_value) => merge(base_value, value),
+          None => value.clone()
+        };
+        merged.insert(key.clone(), value);
+      }
+      Yaml::Hash(merged)
+    },
+    _ => over.clone()
+  }
+}
+
+impl Prefabs {
+  pub fn new() -> Prefabs {
+    Prefabs {
+      components: WorldSerializer::new(),
+      definitions: HashMap::new()
+    }
+  }
+
+  pub fn register<T: Component + Serializable>(&mut self, name: &str) {
+    self.components.register::<T>(name);
+  }
+
+  pub fn contains(&self, name: &str) -> bool {
+    self.definitions.contains_key(name)
+  }
+
+  // prefabs loaded later replace earlier ones with the same name
+  pub fn load_str(&mut self, source: &str) -> Result<(), SerializeError> {
+    let docs = YamlLoader::load_from_str(source)
+      .map_err(|why| SerializeError::Parse(format!("{:?}", why)))?;
+    self.load_docs(docs)
+  }
+
+  pub fn load_file(&mut self, path: &str) -> Result<(), SerializeError> {
+    let docs = config_loader::read_yaml_docs(path).map_err(SerializeError::Io)?;
+    self.load_docs(docs)
+  }
+
+  fn load_docs(&mut self, docs: Vec<Yaml>) -> Result<(), SerializeError> {
+    for doc in docs {
+      let prefabs = match doc {
+        Yaml::Hash(prefabs) => prefabs,
+        _ => return Err(SerializeError::Malformed("prefab file should map prefab names to definitions"))
+      };
+      for (name, definition) in prefabs {
+        let name = name.as_str().ok_or(SerializeError::Malformed("prefab name is not a string"))?;
+        self.definitions.insert(name.into(), definition);
+      }
+    }
+    Ok(())
+  }
+
+  // follows the extends chain; chain starts out with the prefabs the ancestors being spawned are
+  // made from, so cycles through children are caught too, and is left as it was
+  fn resolve(&self, definition: &Yaml, chain: &mut Vec<String>) -> Result<Resolved, SerializeError> {
+    let mut resolved = match definition["extends"].as_str() {
+      Some(base_name) => {
+        if chain.iter().any(|name| name == base_name) {
+          return Err(SerializeError::Malformed("prefab inheritance cycle"));
+        }
+        let base = self.definitions.get(base_name)
+          .ok_or_else(|| SerializeError::UnknownPrefab(base_name.into()))?;
+        chain.push(base_name.into());
+        let resolved = self.resolve(base, chain);
+        chain.pop();
+        resolved?
+      },
+      None => Resolved { components: Hash::new(), children: Vec::new() }
+    };
+    if let Some(components) = definition["components"].as_hash() {
+      let merged = merge(&Yaml::Hash(resolved.components), &Yaml::Hash(components.clone()));
+      resolved.components = merged.into_hash().expect("Error: Merged prefab components are not a hash");
+    }
+    if let Some(children) = definition["children"].as_vec() {
+      resolved.children.extend(children.iter().cloned());
+    }
+    Ok(resolved)
+  }
+
+  pub fn spawn(&self, world: &mut World, name: &str) -> Result<Entity, SerializeError> {
+    let definition = self.definitions.get(name)
+      .ok_or_else(|| SerializeError::UnknownPrefab(name.into()))?;
+    self.spawn_definition(world, definition, Some(name), &mut Vec::new())
+  }
+
+  // a partly built entity is removed again, along with the children attached so far. prefab is
+  // what the definition is made from: the prefab's own name when spawned by name, or the one a
+  // child extends. ancestors holds that of every entity above it.
+  fn spawn_definition(&self, world: &mut World, definition: &Yaml, prefab: Option<&str>, ancestors: &mut Vec<String>)
+    -> Result<Entity, SerializeError> {
+    let e = world.create();
+    let result = self.fill(world, e, definition, prefab, ancestors);
+    if result.is_err() {
+      world.remove(e);
+    }
+    result.map(|_| e)
+  }
+
+  fn fill(&self, world: &mut World, e: Entity, definition: &Yaml, prefab: Option<&str>, ancestors: &mut Vec<String>)
+    -> Result<(), SerializeError> {
+    let resolved = self.resolve(definition, ancestors)?;
+    let entities = EntityMap::new();
+    for (name, data) in &resolved.components {
+      let name = name.as_str().ok_or(SerializeError::Malformed("component name is not a string"))?;
+      self.components.load_component(world, e, name, data, &entities)?;
+    }
+    let depth = ancestors.len();
+    ancestors.extend(prefab.map(String::from));
+    let mut result = Ok(());
+    for child in &resolved.children {
+      match self.spawn_definition(world, child, child["extends"].as_str(), ancestors) {
+        Ok(child) => world.set_parent(child, e),
+        Err(why) => {
+          result = Err(why);
+          break;
+        }
+      }
+    }
+    ancestors.truncate(depth);
+    result
+  }
+}
+
+impl World {
+  pub fn prefabs(&self) -> &Prefabs {
+    &self.prefabs
+  }
+
+  pub fn prefabs_mut(&mut self) -> &mut Prefabs {
+    Rc::get_mut(&mut self.prefabs).expect("Error: Prefabs can't be changed while a prefab is spawning")
+  }
+
+  pub fn spawn_prefab(&mut self, name: &str) -> Entity {
+    self.try_spawn_prefab(name).unwrap_or_else(|why| panic!("Error: Could not spawn prefab {}: {}", name, why))
+  }
+
+  pub fn try_spawn_prefab(&mut self, name: &str) -> Result<Entity, SerializeError> {
+    // a second handle lets the templates be read while the world is modified, and keeps them
+    // in place for hooks that spawn prefabs of their own
+    let prefabs = self.prefabs.clone();
+    prefabs.spawn(self, name)
+  }
+}
+
+#[cfg(test)]
+mod prefab_tests {
+  use yaml_rust::Yaml;
+  use super::super::*;
+
+  // describe: spawning entities from prefabs
+
+  #[derive(PartialEq, Debug)]
+  struct Health {
+    hp: i64
+  }
+
+  impl Component for Health {
+    type Storage = VecStorage<Self>;
+  }
+
+  impl Serializable for Health {
+    fn serialize(&self) -> Yaml {
+      Yaml::Integer(self.hp)
+    }
+
+    fn deserialize(data: &Yaml, _entities: &EntityMap) -> Option<Health> {
+      data.as_i64().map(|hp| Health { hp: hp })
+    }
+  }
+
+  const PREFABS: &'static str = "
+enemy:
+  components:
+    Health: 5
+    Transform:
+      scale: [2.0, 2.0]
+sword:
+  components:
+    Transform:
+      translation: [1.0, 0.0]
+goblin:
+  extends: enemy
+  components:
+    Transform:
+      translation: [4.0, 0.0]
+  children:
+    - extends: sword
+    - components:
+        Health: 1
+";
+
+  fn prefab_world() -> World {
+    let mut test_world = World::new();
+    test_world.prefabs_mut().register::<Health>("Health");
+    test_world.prefabs_mut().load_str(PREFABS).unwrap();
+    test_world
+  }
+
+  // it should spawn an entity with the prefab's components
+  #[test]
+  fn test_spawn_prefab() {
+    let mut test_world = prefab_world();
+    let enemy = test_world.spawn_prefab("enemy");
+    assert_eq!(test_world.get::<Health>(enemy), Some(&Health{ hp: 5 }));
+    assert_eq!(test_world.get::<Transform>(enemy).map(|transform| transform.scale), Some([2.0, 2.0]));
+  }
+
+  // it should merge inherited components and spawn children
+  #[test]
+  fn test_inheritance_and_children() {
+    let mut test_world = prefab_world();
+    let goblin = test_world.spawn_prefab("goblin");
+    assert_eq!(test_world.get::<Health>(goblin), Some(&Health{ hp: 5 }));
+    let transform = *test_world.get::<Transform>(goblin).unwrap();
+    assert_eq!(transform.translation, [4.0, 0.0]);
+    assert_eq!(transform.scale, [2.0, 2.0]);
+    let children = test_world.children(goblin).to_vec();
+    assert_eq!(children.len(), 2);
+    assert_eq!(test_world.get::<Transform>(children[0]).map(|transform| transform.translation), Some([1.0, 0.0]));
+    assert_eq!(test_world.get::<Health>(children[1]), Some(&Health{ hp: 1 }));
+    test_world.remove(goblin);
+    assert_eq!(test_world.iter().count(), 0);
+  }
+
+  // it should let a child extend the same base as its parent
+  #[test]
+  fn test_child_shares_base() {
+    let mut test_world = prefab_world();
+    test_world.prefabs_mut().load_str("boss:\n  extends: enemy\n  children:\n    - extends: enemy\n").unwrap();
+    let boss = test_world.try_spawn_prefab("boss").unwrap();
+    let minions = test_world.children(boss).to_vec();
+    assert_eq!(minions.len(), 1);
+    assert_eq!(test_world.get::<Health>(minions[0]), Some(&Health{ hp: 5 }));
+  }
+
+  // it should report unknown prefabs and components without leaving entities behind
+  #[test]
+  fn test_errors() {
+    let mut test_world = prefab_world();
+    assert_eq!(test_world.try_spawn_prefab("dragon"), Err(SerializeError::UnknownPrefab("dragon".into())));
+    test_world.prefabs_mut().load_str("orc:\n  children:\n    - components:\n        Mana: 3\n").unwrap();
+    assert_eq!(test_world.try_spawn_prefab("orc"), Err(SerializeError::UnknownType("Mana".into())));
+    test_world.prefabs_mut().load_str("a:\n  extends: b\nb:\n  extends: a\n").unwrap();
+    assert_eq!(test_world.try_spawn_prefab("a"), Err(SerializeError::Malformed("prefab inheritance cycle")));
+    test_world.prefabs_mut().load_str("nest:\n  children:\n    - extends: nest\n").unwrap();
+    assert_eq!(test_world.try_spawn_prefab("nest"), Err(SerializeError::Malformed("prefab inheritance cycle")));
+    test_world.prefabs_mut().load_str("egg:\n  children:\n    - extends: hen\nhen:\n  extends: egg\n").unwrap();
+    assert_eq!(test_world.try_spawn_prefab("egg"), Err(SerializeError::Malformed("prefab inheritance cycle")));
+    assert_eq!(test_world.iter().count(), 0);
+    assert_eq!(test_world.prefabs().contains("goblin"), true);
+  }
+
+  // it should let hooks spawn prefabs while one is spawning
+  #[test]
+  fn test_spawn_from_hook() {
+    let mut test_world = prefab_world();
+    test_world.on_add::<Health, _>(|world, _| {
+      world.spawn_prefab("sword");
+    });
+    test_world.spawn_prefab("enemy");
+    assert_eq!(test_world.iter().count(), 2);
+    assert_eq!(test_world.query::<(&Transform, Without<Health>)>().count(), 1);
+  }
+}
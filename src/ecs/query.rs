@@ -0,0 +1,600 @@
+use std::any::TypeId;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_set;
+use std::marker::PhantomData;
+use std::slice;
+
+use std::any;
+
+use super::{Entity, Component, ComponentStore, Store, EcsError, ChangeTicks, assert_writable};
+
+type Components = HashMap<((), TypeId), Box<Store>>;
+
+// Raw pointers to just the stores a query or system touches, looked up ahead of time so that
+// the component map itself never has to be borrowed while the stores are in use
+pub type StorePtrs = HashMap<TypeId, StorePtr>;
+
+// Stores that are only read are never borrowed mutably, so any number of readers can share them
+#[derive(Copy, Clone)]
+pub enum StorePtr {
+  Shared(*const Store),
+  Unique(*mut Store)
+}
+
+// a type in both lists gets a unique pointer
+pub fn store_ptrs(components: &mut Components, reads: &[TypeId], writes: &[TypeId]) -> StorePtrs {
+  let mut stores = HashMap::new();
+  for &ty in writes {
+    if let Some(store) = components.get_mut(&((), ty)) {
+      stores.insert(ty, StorePtr::Unique(&mut **store as *mut Store));
+    }
+  }
+  for &ty in reads {
+    if stores.contains_key(&ty) {
+      continue;
+    }
+    if let Some(store) = components.get(&((), ty)) {
+      stores.insert(ty, StorePtr::Shared(&**store as *const Store));
+    }
+  }
+  stores
+}
+
+// Filter that only matches entities with a T component, without borrowing it
+pub struct With<T: Component>(PhantomData<T>);
+
+// Filter that only matches entities without a T component
+pub struct Without<T: Component>(PhantomData<T>);
+
+// Filter that only matches entities whose T component was added since the reader last looked
+pub struct Added<T: Component>(PhantomData<T>);
+
+// Filter that only matches entities whose T component was added or mutably borrowed since the reader last looked
+pub struct Changed<T: Component>(PhantomData<T>);
+
+pub trait Query<'a> {
+  type Item;
+  type Fetch;
+
+  // filters only look at which entities have a component and when it changed, so they can sit
+  // next to a &mut of the same type
+  fn access(reads: &mut Vec<TypeId>, writes: &mut Vec<TypeId>, filters: &mut Vec<TypeId>);
+
+  // the caller must make sure no component type is fetched mutably more than once
+  unsafe fn fetch(stores: &StorePtrs, ticks: ChangeTicks) -> Result<Self::Fetch, EcsError>;
+
+  // checked for the whole query before anything is fetched, so filters run before &mut T marks a change
+  unsafe fn matches(fetch: &Self::Fetch, e: Entity) -> bool;
+
+  // the entities of the smallest store the query needs a component from, which are the only ones
+  // that can match; None when nothing is required, e.g. for Option and Without
+  unsafe fn entities(fetch: &Self::Fetch) -> Option<&'a [Entity]>;
+
+  // only called once matches passed, so filters don't have to look at their store again
+  unsafe fn get(fetch: &mut Self::Fetch, e: Entity) -> Option<Self::Item>;
+}
+
+// the pointers are cast rather than downcast through a reference, so a filter's pointer stays
+// valid next to a &mut T handed out from the same store
+pub unsafe fn fetch_store<T: Component>(stores: &StorePtrs) -> Option<*const ComponentStore<T>> {
+  let store = match stores.get(&TypeId::of::<T>()) {
+    Some(&StorePtr::Shared(store)) => store,
+    Some(&StorePtr::Unique(store)) => store as *const Store,
+    None => return None
+  };
+  if (*store).is::<ComponentStore<T>>() { Some(store as *const ComponentStore<T>) } else { None }
+}
+
+// None for stores that were only looked up for reading
+pub unsafe fn fetch_store_mut<T: Component>(stores: &StorePtrs) -> Option<*mut ComponentStore<T>> {
+  match stores.get(&TypeId::of::<T>()) {
+    Some(&StorePtr::Unique(store)) if (*store).is::<ComponentStore<T>>() => Some(store as *mut ComponentStore<T>),
+    _ => None
+  }
+}
+
+impl<'a, T: Component> Query<'a> for &'a T {
+  type Item = &'a T;
+  type Fetch = *const ComponentStore<T>;
+
+  fn access(reads: &mut Vec<TypeId>, _writes: &mut Vec<TypeId>, _filters: &mut Vec<TypeId>) {
+    reads.push(TypeId::of::<T>());
+  }
+
+  unsafe fn fetch(stores: &StorePtrs, _ticks: ChangeTicks) -> Result<Self::Fetch, EcsError> {
+    fetch_store::<T>(stores).ok_or(EcsError::UnregisteredComponent(any::type_name::<T>()))
+  }
+
+  unsafe fn matches(fetch: &Self::Fetch, e: Entity) -> bool {
+    (**fetch).contains(e)
+  }
+
+  unsafe fn entities(fetch: &Self::Fetch) -> Option<&'a [Entity]> {
+    Some((**fetch).entities())
+  }
+
+  unsafe fn get(fetch: &mut Self::Fetch, e: Entity) -> Option<Self::Item> {
+    (**fetch).get(e)
+  }
+}
+
+impl<'a, T: Component> Query<'a> for &'a mut T {
+  type Item = &'a mut T;
+  type Fetch = (*mut ComponentStore<T>, usize);
+
+  fn access(_reads: &mut Vec<TypeId>, writes: &mut Vec<TypeId>, _filters: &mut Vec<TypeId>) {
+    assert_writable::<T>();
+    writes.push(TypeId::of::<T>());
+  }
+
+  unsafe fn fetch(stores: &StorePtrs, ticks: ChangeTicks) -> Result<Self::Fetch, EcsError> {
+    fetch_store_mut::<T>(stores)
+      .map(|typed_store| (typed_store, ticks.change_tick))
+      .ok_or(EcsError::UnregisteredComponent(any::type_name::<T>()))
+  }
+
+  unsafe fn matches(fetch: &Self::Fetch, e: Entity) -> bool {
+    (*fetch.0).contains(e)
+  }
+
+  unsafe fn entities(fetch: &Self::Fetch) -> Option<&'a [Entity]> {
+    Some((*fetch.0).entities())
+  }
+
+  // never borrows the whole store, which would invalidate the items already handed out
+  unsafe fn get(fetch: &mut Self::Fetch, e: Entity) -> Option<Self::Item> {
+    ComponentStore::get_ptr(fetch.0, e, fetch.1).map(|comp| &mut *comp)
+  }
+}
+
+impl<'a, T: Component> Query<'a> for Option<&'a T> {
+  type Item = Option<&'a T>;
+  type Fetch = Option<*const ComponentStore<T>>;
+
+  fn access(reads: &mut Vec<TypeId>, _writes: &mut Vec<TypeId>, _filters: &mut Vec<TypeId>) {
+    reads.push(TypeId::of::<T>());
+  }
+
+  unsafe fn fetch(stores: &StorePtrs, _ticks: ChangeTicks) -> Result<Self::Fetch, EcsError> {
+    Ok(fetch_store::<T>(stores))
+  }
+
+  unsafe fn matches(_fetch: &Self::Fetch, _e: Entity) -> bool {
+    true
+  }
+
+  unsafe fn entities(_fetch: &Self::Fetch) -> Option<&'a [Entity]> {
+    None
+  }
+
+  unsafe fn get(fetch: &mut Self::Fetch, e: Entity) -> Option<Self::Item> {
+    Some(fetch.and_then(|typed_store| (*typed_store).get(e)))
+  }
+}
+
+impl<'a, T: Component> Query<'a> for Option<&'a mut T> {
+  type Item = Option<&'a mut T>;
+  type Fetch = (Option<*mut ComponentStore<T>>, usize);
+
+  fn access(_reads: &mut Vec<TypeId>, writes: &mut Vec<TypeId>, _filters: &mut Vec<TypeId>) {
+    assert_writable::<T>();
+    writes.push(TypeId::of::<T>());
+  }
+
+  unsafe fn fetch(stores: &StorePtrs, ticks: ChangeTicks) -> Result<Self::Fetch, EcsError> {
+    Ok((fetch_store_mut::<T>(stores), ticks.change_tick))
+  }
+
+  unsafe fn matches(_fetch: &Self::Fetch, _e: Entity) -> bool {
+    true
+  }
+
+  unsafe fn entities(_fetch: &Self::Fetch) -> Option<&'a [Entity]> {
+    None
+  }
+
+  unsafe fn get(fetch: &mut Self::Fetch, e: Entity) -> Option<Self::Item> {
+    let tick = fetch.1;
+    Some(fetch.0.and_then(|typed_store| ComponentStore::get_ptr(typed_store, e, tick)).map(|comp| &mut *comp))
+  }
+}
+
+impl<'a, T: Component> Query<'a> for With<T> {
+  type Item = ();
+  type Fetch = Option<*const ComponentStore<T>>;
+
+  fn access(_reads: &mut Vec<TypeId>, _writes: &mut Vec<TypeId>, filters: &mut Vec<TypeId>) {
+    filters.push(TypeId::of::<T>());
+  }
+
+  unsafe fn fetch(stores: &StorePtrs, _ticks: ChangeTicks) -> Result<Self::Fetch, EcsError> {
+    Ok(fetch_store::<T>(stores))
+  }
+
+  unsafe fn matches(fetch: &Self::Fetch, e: Entity) -> bool {
+    fetch.map_or(false, |typed_store| (*typed_store).contains(e))
+  }
+
+  unsafe fn entities(fetch: &Self::Fetch) -> Option<&'a [Entity]> {
+    Some(fetch.map_or(&[], |typed_store| (*typed_store).entities()))
+  }
+
+  unsafe fn get(_fetch: &mut Self::Fetch, _e: Entity) -> Option<Self::Item> {
+    Some(())
+  }
+}
+
+impl<'a, T: Component> Query<'a> for Without<T> {
+  type Item = ();
+  type Fetch = Option<*const ComponentStore<T>>;
+
+  fn access(_reads: &mut Vec<TypeId>, _writes: &mut Vec<TypeId>, filters: &mut Vec<TypeId>) {
+    filters.push(TypeId::of::<T>());
+  }
+
+  unsafe fn fetch(stores: &StorePtrs, _ticks: ChangeTicks) -> Result<Self::Fetch, EcsError> {
+    Ok(fetch_store::<T>(stores))
+  }
+
+  unsafe fn matches(fetch: &Self::Fetch, e: Entity) -> bool {
+    fetch.map_or(true, |typed_store| !(*typed_store).contains(e))
+  }
+
+  unsafe fn entities(_fetch: &Self::Fetch) -> Option<&'a [Entity]> {
+    None
+  }
+
+  unsafe fn get(_fetch: &mut Self::Fetch, _e: Entity) -> Option<Self::Item> {
+    Some(())
+  }
+}
+
+impl<'a, T: Component> Query<'a> for Added<T> {
+  type Item = ();
+  type Fetch = (Option<*const ComponentStore<T>>, usize);
+
+  fn access(_reads: &mut Vec<TypeId>, _writes: &mut Vec<TypeId>, filters: &mut Vec<TypeId>) {
+    filters.push(TypeId::of::<T>());
+  }
+
+  unsafe fn fetch(stores: &StorePtrs, ticks: ChangeTicks) -> Result<Self::Fetch, EcsError> {
+    Ok((fetch_store::<T>(stores), ticks.last_change_tick))
+  }
+
+  unsafe fn matches(fetch: &Self::Fetch, e: Entity) -> bool {
+    let (store, since) = *fetch;
+    store.map_or(false, |typed_store| (*typed_store).is_added(e, since))
+  }
+
+  unsafe fn entities(fetch: &Self::Fetch) -> Option<&'a [Entity]> {
+    Some(fetch.0.map_or(&[], |typed_store| (*typed_store).entities()))
+  }
+
+  unsafe fn get(_fetch: &mut Self::Fetch, _e: Entity) -> Option<Self::Item> {
+    Some(())
+  }
+}
+
+impl<'a, T: Component> Query<'a> for Changed<T> {
+  type Item = ();
+  type Fetch = (Option<*const ComponentStore<T>>, usize);
+
+  fn access(_reads: &mut Vec<TypeId>, _writes: &mut Vec<TypeId>, filters: &mut Vec<TypeId>) {
+    filters.push(TypeId::of::<T>());
+  }
+
+  unsafe fn fetch(stores: &StorePtrs, ticks: ChangeTicks) -> Result<Self::Fetch, EcsError> {
+    Ok((fetch_store::<T>(stores), ticks.last_change_tick))
+  }
+
+  unsafe fn matches(fetch: &Self::Fetch, e: Entity) -> bool {
+    let (store, since) = *fetch;
+    store.map_or(false, |typed_store| (*typed_store).is_changed(e, since))
+  }
+
+  unsafe fn entities(fetch: &Self::Fetch) -> Option<&'a [Entity]> {
+    Some(fetch.0.map_or(&[], |typed_store| (*typed_store).entities()))
+  }
+
+  unsafe fn get(_fetch: &mut Self::Fetch, _e: Entity) -> Option<Self::Item> {
+    Some(())
+  }
+}
+
+macro_rules! impl_query_tuple {
+  ($($name:ident),+) => {
+    impl<'a, $($name: Query<'a>),+> Query<'a> for ($($name,)+) {
+      type Item = ($($name::Item,)+);
+      type Fetch = ($($name::Fetch,)+);
+
+      fn access(reads: &mut Vec<TypeId>, writes: &mut Vec<TypeId>, filters: &mut Vec<TypeId>) {
+        $($name::access(reads, writes, filters);)+
+      }
+
+      unsafe fn fetch(stores: &StorePtrs, ticks: ChangeTicks) -> Result<Self::Fetch, EcsError> {
+        Ok(($($name::fetch(stores, ticks)?,)+))
+      }
+
+      #[allow(non_snake_case)]
+      unsafe fn matches(fetch: &Self::Fetch, e: Entity) -> bool {
+        let ($(ref $name,)+) = *fetch;
+        $($name::matches($name, e))&&+
+      }
+
+      #[allow(non_snake_case)]
+      unsafe fn entities(fetch: &Self::Fetch) -> Option<&'a [Entity]> {
+        let ($(ref $name,)+) = *fetch;
+        let mut smallest: Option<&'a [Entity]> = None;
+        $(
+          if let Some(entities) = $name::entities($name) {
+            if smallest.map_or(true, |current| entities.len() < current.len()) {
+              smallest = Some(entities);
+            }
+          }
+        )+
+        smallest
+      }
+
+      #[allow(non_snake_case)]
+      unsafe fn get(fetch: &mut Self::Fetch, e: Entity) -> Option<Self::Item> {
+        let ($(ref mut $name,)+) = *fetch;
+        Some(($(
+          match $name::get($name, e) {
+            Some(item) => item,
+            None => return None
+          },
+        )+))
+      }
+    }
+  }
+}
+
+impl_query_tuple!(A);
+impl_query_tuple!(A, B);
+impl_query_tuple!(A, B, C);
+impl_query_tuple!(A, B, C, D);
+impl_query_tuple!(A, B, C, D, E);
+impl_query_tuple!(A, B, C, D, E, F);
+impl_query_tuple!(A, B, C, D, E, F, G);
+impl_query_tuple!(A, B, C, D, E, F, G, H);
+
+// stores only hold live entities, so walking one skips the active set entirely
+enum Candidates<'a> {
+  Stored(slice::Iter<'a, Entity>),
+  Active(hash_set::Iter<'a, Entity>)
+}
+
+impl<'a> Iterator for Candidates<'a> {
+  type Item = &'a Entity;
+
+  fn next(&mut self) -> Option<&'a Entity> {
+    match *self {
+      Candidates::Stored(ref mut entities) => entities.next(),
+      Candidates::Active(ref mut entities) => entities.next()
+    }
+  }
+}
+
+pub struct QueryIter<'a, Q: Query<'a>> {
+  entities: Candidates<'a>,
+  fetch: Q::Fetch
+}
+
+// filters count as reads
+pub fn query_access<'a, Q: Query<'a>>() -> (Vec<TypeId>, Vec<TypeId>) {
+  let mut reads = Vec::new();
+  let mut writes = Vec::new();
+  let mut filters = Vec::new();
+  Q::access(&mut reads, &mut writes, &mut filters);
+  for (i, write) in writes.iter().enumerate() {
+    if reads.contains(write) || writes[i + 1..].contains(write) {
+      panic!("Error: Query borrows a component type mutably more than once")
+    }
+  }
+  reads.extend(filters);
+  (reads, writes)
+}
+
+impl<'a, Q: Query<'a>> QueryIter<'a, Q> {
+  // the stores must stay valid and unaliased for 'a; query_access checks the query itself
+  pub unsafe fn new(active: &'a HashSet<Entity>, stores: &StorePtrs, ticks: ChangeTicks) -> Result<QueryIter<'a, Q>, EcsError> {
+    let fetch = Q::fetch(stores, ticks)?;
+    let entities = match Q::entities(&fetch) {
+      Some(entities) => Candidates::Stored(entities.iter()),
+      None => Candidates::Active(active.iter())
+    };
+    Ok(QueryIter {
+      entities: entities,
+      fetch: fetch
+    })
+  }
+}
+
+impl<'a, Q: Query<'a>> Iterator for QueryIter<'a, Q> {
+  type Item = (Entity, Q::Item);
+
+  fn next(&mut self) -> Option<(Entity, Q::Item)> {
+    while let Some(&e) = self.entities.next() {
+      if unsafe { Q::matches(&self.fetch, e) } {
+        if let Some(item) = unsafe { Q::get(&mut self.fetch, e) } {
+          return Some((e, item));
+        }
+      }
+    }
+    None
+  }
+}
+
+#[cfg(test)]
+mod query_tests {
+  use super::super::*;
+
+  // describe: a query over an ECS World
+
+  struct Position {
+    x: usize
+  }
+
+  impl Component for Position {
+    type Storage = VecStorage<Self>;
+  }
+
+  struct Velocity {
+    dx: usize
+  }
+
+  impl Component for Velocity {
+    type Storage = SparseSetStorage<Self>;
+  }
+
+  fn setup_world() -> (World, Entity, Entity, Entity) {
+    let mut test_world = World::new();
+    test_world.register_comp::<Position>();
+    test_world.register_comp::<Velocity>();
+    let moving = test_world.create();
+    test_world.add_comp(moving, Position{ x: 1 });
+    test_world.add_comp(moving, Velocity{ dx: 2 });
+    let still = test_world.create();
+    test_world.add_comp(still, Position{ x: 5 });
+    let ghost = test_world.create();
+    test_world.add_comp(ghost, Velocity{ dx: 3 });
+    (test_world, moving, still, ghost)
+  }
+
+  // it should only yield entities that have every queried component
+  #[test]
+  fn test_join() {
+    let (mut test_world, moving, _, _) = setup_world();
+    let results: Vec<_> = test_world.query::<(&Position, &Velocity)>()
+      .map(|(e, (pos, vel))| (e, pos.x, vel.dx))
+      .collect();
+    assert_eq!(results, vec![(moving, 1, 2)]);
+  }
+
+  // it should allow one side of the join to be mutated
+  #[test]
+  fn test_join_mut() {
+    let (mut test_world, moving, _, _) = setup_world();
+    for (_, (pos, vel)) in test_world.query::<(&mut Position, &Velocity)>() {
+      pos.x += vel.dx;
+    }
+    assert_eq!(test_world.get::<Position>(moving).map(|pos| pos.x), Some(3));
+  }
+
+  // it should yield every entity with the required component when the rest are optional
+  #[test]
+  fn test_optional() {
+    let (mut test_world, moving, still, _) = setup_world();
+    let mut results: Vec<_> = test_world.query::<(&Position, Option<&Velocity>)>()
+      .map(|(e, (pos, vel))| (e, pos.x, vel.map(|vel| vel.dx)))
+      .collect();
+    results.sort();
+    assert_eq!(results, vec![(moving, 1, Some(2)), (still, 5, None)]);
+  }
+
+  // it should filter entities with With and Without
+  #[test]
+  fn test_filters() {
+    let (mut test_world, moving, still, _) = setup_world();
+    let with: Vec<_> = test_world.query::<(&Position, With<Velocity>)>().map(|(e, _)| e).collect();
+    assert_eq!(with, vec![moving]);
+    let without: Vec<_> = test_world.query::<(&Position, Without<Velocity>)>().map(|(e, _)| e).collect();
+    assert_eq!(without, vec![still]);
+  }
+
+  // it should fall back to every live entity when the query requires no component
+  #[test]
+  fn test_unrequired() {
+    let (mut test_world, _, still, _) = setup_world();
+    let without: Vec<_> = test_world.query::<Without<Velocity>>().map(|(e, _)| e).collect();
+    assert_eq!(without, vec![still]);
+    assert_eq!(test_world.query::<Option<&Velocity>>().count(), 3);
+  }
+
+  // it should not yield removed entities
+  #[test]
+  fn test_removed() {
+    let (mut test_world, moving, _, _) = setup_world();
+    test_world.remove(moving);
+    assert_eq!(test_world.query::<(&Position, &Velocity)>().count(), 0);
+  }
+
+  // it should only match components added or changed since the trackers were last cleared
+  #[test]
+  fn test_change_filters() {
+    let (mut test_world, moving, still, ghost) = setup_world();
+    let added: Vec<_> = test_world.query::<(&Position, Added<Position>)>().map(|(e, _)| e).collect();
+    assert_eq!(added.len(), 2);
+    test_world.clear_trackers();
+    assert_eq!(test_world.query::<Added<Position>>().count(), 0);
+    assert_eq!(test_world.query::<Changed<Position>>().count(), 0);
+    if let Some(pos) = test_world.get_mut::<Position>(still) {
+      pos.x += 1;
+    }
+    let changed: Vec<_> = test_world.query::<(&Position, Changed<Position>)>().map(|(e, _)| e).collect();
+    assert_eq!(changed, vec![still]);
+    test_world.add_comp(ghost, Position{ x: 0 });
+    let mut changed: Vec<_> = test_world.query::<Changed<Position>>().map(|(e, _)| e).collect();
+    changed.sort();
+    assert_eq!(changed, vec![still, ghost]);
+    let added: Vec<_> = test_world.query::<Added<Position>>().map(|(e, _)| e).collect();
+    assert_eq!(added, vec![ghost]);
+    test_world.clear_trackers();
+    for (_, (pos, _)) in test_world.query::<(&mut Position, With<Velocity>)>() {
+      pos.x += 1;
+    }
+    let changed: Vec<_> = test_world.query::<Changed<Position>>().map(|(e, _)| e).collect();
+    assert_eq!(changed.len(), 2);
+    assert_eq!(changed.contains(&moving), true);
+  }
+
+  // it should let a query mutate the components its change filter matched
+  #[test]
+  fn test_mutate_changed() {
+    let (mut test_world, moving, still, _) = setup_world();
+    test_world.clear_trackers();
+    if let Some(pos) = test_world.get_mut::<Position>(still) {
+      pos.x += 1;
+    }
+    for (_, (pos, _)) in test_world.query::<(&mut Position, Changed<Position>)>() {
+      pos.x *= 10;
+    }
+    assert_eq!(test_world.get::<Position>(still).map(|pos| pos.x), Some(60));
+    assert_eq!(test_world.get::<Position>(moving).map(|pos| pos.x), Some(1));
+  }
+
+  // it should keep every mutable item valid while the others are handed out
+  #[test]
+  fn test_collect_mut() {
+    let (mut test_world, moving, still, ghost) = setup_world();
+    test_world.add_comp(still, Velocity{ dx: 4 });
+    let items: Vec<_> = test_world.query::<(&mut Position, Option<&mut Velocity>)>().collect();
+    for (_, (pos, vel)) in items {
+      if let Some(vel) = vel {
+        pos.x += vel.dx;
+        vel.dx = 0;
+      }
+    }
+    assert_eq!(test_world.get::<Position>(moving).map(|pos| pos.x), Some(3));
+    assert_eq!(test_world.get::<Position>(still).map(|pos| pos.x), Some(9));
+    assert_eq!(test_world.get::<Velocity>(ghost).map(|vel| vel.dx), Some(3));
+    assert_eq!(test_world.query::<&Velocity>().filter(|&(_, vel)| vel.dx == 0).count(), 2);
+  }
+
+  // it should panic when a query borrows the same component type mutably twice
+  #[test]
+  #[should_panic(expected = "Error: Query borrows a component type mutably more than once")]
+  fn test_aliased_query() {
+    let (mut test_world, _, _, _) = setup_world();
+    test_world.query::<(&mut Position, &Position)>();
+  }
+
+  // it should panic when querying an unregistered component
+  #[test]
+  #[should_panic(expected = "Error: Could not find component of given type to query")]
+  fn test_query_no_registration() {
+    let mut test_world = World::new();
+    test_world.create();
+    test_world.query::<&Position>();
+  }
+}
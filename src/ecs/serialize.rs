@@ -0,0 +1,668 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::str;
+use std::f64;
+use std::u32;
+use yaml_rust::{Yaml, YamlLoader, YamlEmitter};
+use yaml_rust::yaml::Hash;
+
+use super::{Entity, Component, Resource, World, Parent, Children, Transform, Name};
+
+// Opt-in conversion to and from YAML, used for both the YAML and the binary save formats.
+// Entity references should be written with entity_to_yaml and read back through the EntityMap.
+pub trait Serializable: Sized {
+  fn serialize(&self) -> Yaml;
+  fn deserialize(data: &Yaml, entities: &EntityMap) -> Option<Self>;
+}
+
+pub fn entity_to_yaml(e: Entity) -> Yaml {
+  Yaml::Integer(e.id() as i64)
+}
+
+// Debug formatting keeps the decimal point, so whole numbers don't come back as integers
+pub fn float_to_yaml(value: f64) -> Yaml {
+  Yaml::Real(format!("{:?}", value))
+}
+
+pub fn yaml_to_float(data: &Yaml) -> Option<f64> {
+  match *data {
+    Yaml::Integer(value) => Some(value as f64),
+    Yaml::Real(ref value) => parse_real(value),
+    _ => None
+  }
+}
+
+// also takes YAML's own spellings of infinity and NaN, which Rust's parser doesn't know
+fn parse_real(value: &str) -> Option<f64> {
+  match value {
+    ".inf" | ".Inf" | ".INF" | "+.inf" | "+.Inf" | "+.INF" => Some(f64::INFINITY),
+    "-.inf" | "-.Inf" | "-.INF" => Some(f64::NEG_INFINITY),
+    ".nan" | ".NaN" | ".NAN" => Some(f64::NAN),
+    _ => value.parse::<f64>().ok()
+  }
+}
+
+// Maps the entity ids stored in saved data to the entities created for them while loading
+pub struct EntityMap {
+  entities: HashMap<i64, Entity>
+}
+
+impl EntityMap {
+  pub fn new() -> EntityMap {
+    EntityMap { entities: HashMap::new() }
+  }
+
+  pub fn entity(&self, data: &Yaml) -> Option<Entity> {
+    data.as_i64().and_then(|id| self.entities.get(&id).cloned())
+  }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SaveFormat {
+  Yaml,
+  Binary
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum SerializeError {
+  Io(String),
+  Parse(String),
+  Malformed(&'static str),
+  UnknownType(String),
+  InvalidData(String),
+  UnknownPrefab(String)
+}
+
+impl fmt::Display for SerializeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      SerializeError::Io(ref why) =>
+        write!(f, "could not access save file: {}", why),
+      SerializeError::Parse(ref why) =>
+        write!(f, "could not parse saved world: {}", why),
+      SerializeError::Malformed(why) =>
+        write!(f, "saved world is malformed: {}", why),
+      SerializeError::UnknownType(ref name) =>
+        write!(f, "no serializer registered for {}", name),
+      SerializeError::InvalidData(ref name) =>
+        write!(f, "saved data for {} could not be deserialized", name),
+      SerializeError::UnknownPrefab(ref name) =>
+        write!(f, "no prefab named {} has been loaded", name)
+    }
+  }
+}
+
+impl Error for SerializeError {
+  fn description(&self) -> &str {
+    match *self {
+      SerializeError::Io(_) => "save file io error",
+      SerializeError::Parse(_) => "save file parse error",
+      SerializeError::Malformed(_) => "malformed saved world",
+      SerializeError::UnknownType(_) => "unregistered serializer",
+      SerializeError::InvalidData(_) => "invalid saved data",
+      SerializeError::UnknownPrefab(_) => "unknown prefab"
+    }
+  }
+}
+
+// a decoded component or resource, added to the world once the rest of the data has decoded too
+type Staged = Box<FnOnce(&mut World)>;
+
+struct ComponentSerializer {
+  name: String,
+  save: Box<Fn(&World, Entity) -> Option<Yaml>>,
+  decode: Box<Fn(Entity, &Yaml, &EntityMap) -> Option<Staged>>
+}
+
+struct ResourceSerializer {
+  name: String,
+  save: Box<Fn(&World) -> Option<Yaml>>,
+  decode: Box<Fn(&Yaml, &EntityMap) -> Option<Staged>>
+}
+
+// Saves and loads the current state's entities, components and resources. Only types registered
+// here are written; Parent, Children, Transform and Name are registered from the start.
+pub struct WorldSerializer {
+  components: Vec<ComponentSerializer>,
+  resources: Vec<ResourceSerializer>
+}
+
+impl WorldSerializer {
+  pub fn new() -> WorldSerializer {
+    let mut serializer = WorldSerializer::empty();
+    serializer.register::<Parent>("Parent");
+    serializer.register::<Children>("Children");
+    serializer.register::<Transform>("Transform");
+    serializer.register::<Name>("Name");
+    serializer
+  }
+
+  // without the built-in component serializers
+  pub fn empty() -> WorldSerializer {
+    WorldSerializer {
+      components: Vec::new(),
+      resources: Vec::new()
+    }
+  }
+
+  // the name is what appears in the saved data, so it should stay stable between versions
+  pub fn register<T: Component + Serializable>(&mut self, name: &str) {
+    if self.components.iter().any(|serializer| serializer.name == name) {
+      panic!("Error: A component serializer named {} was already registered", name)
+    }
+    self.components.push(ComponentSerializer {
+      name: name.into(),
+      save: Box::new(|world, e| world.get::<T>(e).map(|comp| comp.serialize())),
+      decode: Box::new(|e, data, entities| {
+        T::deserialize(data, entities).map(|comp| {
+          Box::new(move |world: &mut World| {
+            world.ensure_registered::<T>();
+            world.add_comp(e, comp);
+          }) as Staged
+        })
+      })
+    });
+  }
+
+  // only resources of the current state are saved; global resources are left alone
+  pub fn register_resource<R: Resource + Serializable>(&mut self, name: &str) {
+    if self.resources.iter().any(|serializer| serializer.name == name) {
+      panic!("Error: A resource serializer named {} was already registered", name)
+    }
+    self.resources.push(ResourceSerializer {
+      name: name.into(),
+      save: Box::new(|world| {
+        world.current_state().resources.get(&TypeId::of::<R>())
+          .and_then(|res| res.downcast_ref::<R>())
+          .map(|res| res.serialize())
+      }),
+      decode: Box::new(|data, entities| {
+        R::deserialize(data, entities).map(|res| Box::new(move |world: &mut World| world.insert_resource(res)) as Staged)
+      })
+    });
+  }
+
+  pub fn to_yaml(&self, world: &World) -> Yaml {
+    let mut entities: Vec<Entity> = world.iter().cloned().collect();
+    entities.sort_by_key(|e| e.id());
+    let saved_entities = entities.into_iter().map(|e| {
+      let mut components = Hash::new();
+      for serializer in &self.components {
+        if let Some(data) = (serializer.save)(world, e) {
+          components.insert(Yaml::String(serializer.name.clone()), data);
+        }
+      }
+      let mut entry = Hash::new();
+      entry.insert(Yaml::String("id".into()), entity_to_yaml(e));
+      entry.insert(Yaml::String("components".into()), Yaml::Hash(components));
+      Yaml::Hash(entry)
+    }).collect();
+    let mut resources = Hash::new();
+    for serializer in &self.resources {
+      if let Some(data) = (serializer.save)(world) {
+        resources.insert(Yaml::String(serializer.name.clone()), data);
+      }
+    }
+    let mut doc = Hash::new();
+    doc.insert(Yaml::String("entities".into()), Yaml::Array(saved_entities));
+    doc.insert(Yaml::String("resources".into()), Yaml::Hash(resources));
+    Yaml::Hash(doc)
+  }
+
+  // Adds the saved entities to the current state under fresh ids and returns them. Everything is
+  // decoded before the world is touched, so a load that fails leaves it as it was and runs no hooks.
+  pub fn from_yaml(&self, world: &mut World, doc: &Yaml) -> Result<Vec<Entity>, SerializeError> {
+    let mut reserved = Vec::new();
+    let staged = match self.decode_doc(world, doc, &mut reserved) {
+      Ok(staged) => staged,
+      Err(why) => {
+        world.release(&reserved);
+        return Err(why);
+      }
+    };
+    for &e in &reserved {
+      world.run_entity_created(e);
+    }
+    for insert in staged {
+      insert(world);
+    }
+    Ok(reserved)
+  }
+
+  // the entities are only reserved, so that references between them can be resolved while decoding
+  fn decode_doc(&self, world: &mut World, doc: &Yaml, reserved: &mut Vec<Entity>) -> Result<Vec<Staged>, SerializeError> {
+    let saved_entities = doc["entities"].as_vec().map_or(&[][..], |entities| &entities[..]);
+    let mut entities = EntityMap::new();
+    for entry in saved_entities {
+      let id = entry["id"].as_i64().ok_or(SerializeError::Malformed("entity without an id"))?;
+      if entities.entities.contains_key(&id) {
+        return Err(SerializeError::Malformed("duplicate entity id"));
+      }
+      let e = world.allocate().map_err(|_| SerializeError::Malformed("no world state to load into"))?;
+      reserved.push(e);
+      entities.entities.insert(id, e);
+    }
+    let mut staged = Vec::new();
+    for entry in saved_entities {
+      let e = entities.entity(&entry["id"]).expect("Error: Saved entity was not reserved");
+      if let Some(components) = entry["components"].as_hash() {
+        for (name, data) in components {
+          let name = name.as_str().ok_or(SerializeError::Malformed("component name is not a string"))?;
+          staged.push(self.decode_component(e, name, data, &entities)?);
+        }
+      }
+    }
+    if let Some(resources) = doc["resources"].as_hash() {
+      for (name, data) in resources {
+        let name = name.as_str().ok_or(SerializeError::Malformed("resource name is not a string"))?;
+        let serializer = self.resources.iter().find(|serializer| serializer.name == name)
+          .ok_or_else(|| SerializeError::UnknownType(name.into()))?;
+        staged.push((serializer.decode)(data, &entities).ok_or_else(|| SerializeError::InvalidData(name.into()))?);
+      }
+    }
+    Ok(staged)
+  }
+
+  fn decode_component(&self, e: Entity, name: &str, data: &Yaml, entities: &EntityMap) -> Result<Staged, SerializeError> {
+    let serializer = self.components.iter().find(|serializer| serializer.name == name)
+      .ok_or_else(|| SerializeError::UnknownType(name.into()))?;
+    (serializer.decode)(e, data, entities).ok_or_else(|| SerializeError::InvalidData(name.into()))
+  }
+
+  // deserializes a single component by its registered name and adds it to the entity
+  pub fn load_component(&self, world: &mut World, e: Entity, name: &str, data: &Yaml, entities: &EntityMap)
+    -> Result<(), SerializeError> {
+    let insert = self.decode_component(e, name, data, entities)?;
+    insert(world);
+    Ok(())
+  }
+
+  pub fn save_yaml(&self, world: &World) -> String {
+    let mut out = String::new();
+    YamlEmitter::new(&mut out).dump(&self.to_yaml(world))
+      .unwrap_or_else(|e| panic!("Error: Failed to emit saved world as YAML: {:?}", e));
+    out
+  }
+
+  pub fn load_yaml(&self, world: &mut World, source: &str) -> Result<Vec<Entity>, SerializeError> {
+    let docs = YamlLoader::load_from_str(source)
+      .map_err(|why| SerializeError::Parse(why.description().into()))?;
+    let doc = docs.get(0).ok_or(SerializeError::Malformed("no YAML document"))?;
+    self.from_yaml(world, doc)
+  }
+
+  pub fn save_binary(&self, world: &World) -> Result<Vec<u8>, SerializeError> {
+    let mut out = BINARY_MAGIC.to_vec();
+    encode(&self.to_yaml(world), 0, &mut out)?;
+    Ok(out)
+  }
+
+  pub fn load_binary(&self, world: &mut World, bytes: &[u8]) -> Result<Vec<Entity>, SerializeError> {
+    if !bytes.starts_with(BINARY_MAGIC) {
+      return Err(SerializeError::Malformed("missing binary save header"));
+    }
+    let mut decoder = Decoder { bytes: &bytes[BINARY_MAGIC.len()..] };
+    let doc = decoder.decode(0)?;
+    self.from_yaml(world, &doc)
+  }
+
+  pub fn save_file(&self, world: &World, path: &str, format: SaveFormat) -> Result<(), SerializeError> {
+    let bytes = match format {
+      SaveFormat::Yaml => self.save_yaml(world).into_bytes(),
+      SaveFormat::Binary => self.save_binary(world)?
+    };
+    File::create(path)
+      .and_then(|mut file| file.write_all(&bytes))
+      .map_err(|why| SerializeError::Io(format!("{}: {}", path, why)))
+  }
+
+  pub fn load_file(&self, world: &mut World, path: &str, format: SaveFormat) -> Result<Vec<Entity>, SerializeError> {
+    let mut bytes = Vec::new();
+    File::open(path)
+      .and_then(|mut file| file.read_to_end(&mut bytes))
+      .map_err(|why| SerializeError::Io(format!("{}: {}", path, why)))?;
+    match format {
+      SaveFormat::Yaml => {
+        let source = str::from_utf8(&bytes).map_err(|_| SerializeError::Malformed("save file is not valid UTF-8"))?;
+        self.load_yaml(world, source)
+      },
+      SaveFormat::Binary => self.load_binary(world, &bytes)
+    }
+  }
+}
+
+const BINARY_MAGIC: &'static [u8] = b"SPEW\x01";
+
+const TAG_NULL: u8 = 0;
+const TAG_FALSE: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_INTEGER: u8 = 3;
+const TAG_REAL: u8 = 4;
+const TAG_STRING: u8 = 5;
+const TAG_ARRAY: u8 = 6;
+const TAG_HASH: u8 = 7;
+
+// how deep arrays and hashes may nest, so corrupt data can't recurse until the stack overflows
+const MAX_DEPTH: usize = 128;
+
+fn encode_len(len: usize, out: &mut Vec<u8>) -> Result<(), SerializeError> {
+  if len > u32::MAX as usize {
+    return Err(SerializeError::Malformed("string, array or hash too long for the binary format"));
+  }
+  let len = len as u32;
+  out.extend_from_slice(&[len as u8, (len >> 8) as u8, (len >> 16) as u8, (len >> 24) as u8]);
+  Ok(())
+}
+
+fn encode(data: &Yaml, depth: usize, out: &mut Vec<u8>) -> Result<(), SerializeError> {
+  if depth > MAX_DEPTH {
+    return Err(SerializeError::Malformed("arrays and hashes nested too deep for the binary format"));
+  }
+  match *data {
+    Yaml::Boolean(false) => out.push(TAG_FALSE),
+    Yaml::Boolean(true) => out.push(TAG_TRUE),
+    Yaml::Integer(value) => {
+      out.push(TAG_INTEGER);
+      out.extend((0..8).map(|byte| (value >> (byte * 8)) as u8));
+    },
+    Yaml::Real(ref value) => {
+      // stored as the bit pattern of the parsed float
+      let bits = parse_real(value).ok_or(SerializeError::Malformed("real number that can't be parsed"))?.to_bits();
+      out.push(TAG_REAL);
+      out.extend((0..8).map(|byte| (bits >> (byte * 8)) as u8));
+    },
+    Yaml::String(ref value) => {
+      out.push(TAG_STRING);
+      encode_len(value.len(), out)?;
+      out.extend_from_slice(value.as_bytes());
+    },
+    Yaml::Array(ref items) => {
+      out.push(TAG_ARRAY);
+      encode_len(items.len(), out)?;
+      for item in items {
+        encode(item, depth + 1, out)?;
+      }
+    },
+    Yaml::Hash(ref entries) => {
+      out.push(TAG_HASH);
+      encode_len(entries.len(), out)?;
+      for (key, value) in entries {
+        encode(key, depth + 1, out)?;
+        encode(value, depth + 1, out)?;
+      }
+    },
+    Yaml::Null | Yaml::Alias(_) | Yaml::BadValue => out.push(TAG_NULL)
+  }
+  Ok(())
+}
+
+struct Decoder<'a> {
+  bytes: &'a [u8]
+}
+
+impl<'a> Decoder<'a> {
+  fn take(&mut self, count: usize) -> Result<&'a [u8], SerializeError> {
+    if self.bytes.len() < count {
+      return Err(SerializeError::Malformed("binary save data ends early"));
+    }
+    let (taken, rest) = self.bytes.split_at(count);
+    self.bytes = rest;
+    Ok(taken)
+  }
+
+  fn u64(&mut self) -> Result<u64, SerializeError> {
+    let bytes = self.take(8)?;
+    Ok(bytes.iter().rev().fold(0, |value, &byte| (value << 8) | byte as u64))
+  }
+
+  fn len(&mut self) -> Result<usize, SerializeError> {
+    let bytes = self.take(4)?;
+    Ok(bytes.iter().rev().fold(0, |value, &byte| (value << 8) | byte as usize))
+  }
+
+  fn decode(&mut self, depth: usize) -> Result<Yaml, SerializeError> {
+    if depth > MAX_DEPTH {
+      return Err(SerializeError::Malformed("binary save data nested too deep"));
+    }
+    let tag = self.take(1)?[0];
+    match tag {
+      TAG_NULL => Ok(Yaml::Null),
+      TAG_FALSE => Ok(Yaml::Boolean(false)),
+      TAG_TRUE => Ok(Yaml::Boolean(true)),
+      TAG_INTEGER => self.u64().map(|value| Yaml::Integer(value as i64)),
+      TAG_REAL => self.u64().map(|bits| float_to_yaml(f64::from_bits(bits))),
+      TAG_STRING => {
+        let len = self.len()?;
+        let bytes = self.take(len)?;
+        str::from_utf8(bytes)
+          .map(|value| Yaml::String(value.into()))
+          .map_err(|_| SerializeError::Malformed("binary save string is not valid UTF-8"))
+      },
+      TAG_ARRAY => {
+        let len = self.len()?;
+        let mut items = Vec::new();
+        for _ in 0..len {
+          items.push(self.decode(depth + 1)?);
+        }
+        Ok(Yaml::Array(items))
+      },
+      TAG_HASH => {
+        let len = self.len()?;
+        let mut entries = Hash::new();
+        for _ in 0..len {
+          let key = self.decode(depth + 1)?;
+          let value = self.decode(depth + 1)?;
+          entries.insert(key, value);
+        }
+        Ok(Yaml::Hash(entries))
+      },
+      _ => Err(SerializeError::Malformed("unknown tag in binary save data"))
+    }
+  }
+}
+
+#[cfg(test)]
+mod serialize_tests {
+  use std::f64;
+  use std::sync::{Arc, Mutex};
+  use yaml_rust::Yaml;
+  use yaml_rust::yaml::Hash;
+  use super::super::*;
+
+  // describe: saving and loading a world
+
+  #[derive(PartialEq, Debug)]
+  struct Health {
+    hp: i64
+  }
+
+  impl Component for Health {
+    type Storage = VecStorage<Self>;
+  }
+
+  impl Serializable for Health {
+    fn serialize(&self) -> Yaml {
+      Yaml::Integer(self.hp)
+    }
+
+    fn deserialize(data: &Yaml, _entities: &EntityMap) -> Option<Health> {
+      data.as_i64().map(|hp| Health { hp: hp })
+    }
+  }
+
+  // refers to another entity, so it has to survive remapping
+  struct Target(Entity);
+
+  impl Component for Target {
+    type Storage = HashMapStorage<Self>;
+  }
+
+  impl Serializable for Target {
+    fn serialize(&self) -> Yaml {
+      entity_to_yaml(self.0)
+    }
+
+    fn deserialize(data: &Yaml, entities: &EntityMap) -> Option<Target> {
+      entities.entity(data).map(Target)
+    }
+  }
+
+  #[derive(PartialEq, Debug)]
+  struct Score {
+    points: f64,
+    label: String
+  }
+
+  impl Serializable for Score {
+    fn serialize(&self) -> Yaml {
+      let mut hash = Hash::new();
+      hash.insert(Yaml::String("points".into()), float_to_yaml(self.points));
+      hash.insert(Yaml::String("label".into()), Yaml::String(self.label.clone()));
+      Yaml::Hash(hash)
+    }
+
+    fn deserialize(data: &Yaml, _entities: &EntityMap) -> Option<Score> {
+      match (yaml_to_float(&data["points"]), data["label"].as_str()) {
+        (Some(points), Some(label)) => Some(Score { points: points, label: label.into() }),
+        _ => None
+      }
+    }
+  }
+
+  fn serializer() -> WorldSerializer {
+    let mut serializer = WorldSerializer::new();
+    serializer.register::<Health>("Health");
+    serializer.register::<Target>("Target");
+    serializer.register_resource::<Score>("Score");
+    serializer
+  }
+
+  fn saved_world() -> World {
+    let mut test_world = World::new();
+    let hunter = test_world.spawn_bundle((Health{ hp: 10 }, Transform::at(1.0, 2.0)));
+    let prey = test_world.spawn_bundle((Health{ hp: 3 },));
+    test_world.register_comp::<Target>();
+    test_world.add_comp(hunter, Target(prey));
+    test_world.set_parent(prey, hunter);
+    test_world.insert_resource(Score { points: 2.0, label: "round".into() });
+    test_world
+  }
+
+  fn check_loaded(loaded: &World, entities: &[Entity]) {
+    let hunter = entities[0];
+    let prey = entities[1];
+    assert_eq!(loaded.get::<Health>(hunter), Some(&Health{ hp: 10 }));
+    assert_eq!(loaded.get::<Health>(prey), Some(&Health{ hp: 3 }));
+    assert_eq!(loaded.get::<Target>(hunter).map(|target| target.0), Some(prey));
+    assert_eq!(loaded.get::<Transform>(hunter), Some(&Transform::at(1.0, 2.0)));
+    assert_eq!(loaded.parent(prey), Some(hunter));
+    assert_eq!(loaded.children(hunter), &[prey]);
+    assert_eq!(loaded.resource::<Score>(), Some(&Score { points: 2.0, label: "round".into() }));
+  }
+
+  // it should round trip a world through YAML, remapping entity references
+  #[test]
+  fn test_yaml_round_trip() {
+    let serializer = serializer();
+    let saved = serializer.save_yaml(&saved_world());
+    let mut loaded = World::new();
+    let existing = loaded.create();
+    let entities = serializer.load_yaml(&mut loaded, &saved).unwrap();
+    assert_eq!(entities.len(), 2);
+    assert!(!entities.contains(&existing));
+    check_loaded(&loaded, &entities);
+  }
+
+  // it should round trip a world through the binary format
+  #[test]
+  fn test_binary_round_trip() {
+    let serializer = serializer();
+    let saved = serializer.save_binary(&saved_world()).unwrap();
+    let mut loaded = World::new();
+    loaded.create();
+    let entities = serializer.load_binary(&mut loaded, &saved).unwrap();
+    check_loaded(&loaded, &entities);
+    assert_eq!(serializer.load_binary(&mut loaded, &saved[..saved.len() - 1]).err(),
+      Some(SerializeError::Malformed("binary save data ends early")));
+  }
+
+  // it should refuse binary data nested deeper than the format allows instead of overflowing the stack
+  #[test]
+  fn test_binary_nesting() {
+    let serializer = serializer();
+    let mut bytes = super::BINARY_MAGIC.to_vec();
+    for _ in 0..100000 {
+      // an array holding one item
+      bytes.extend_from_slice(&[super::TAG_ARRAY, 1, 0, 0, 0]);
+    }
+    assert_eq!(serializer.load_binary(&mut World::new(), &bytes).err(),
+      Some(SerializeError::Malformed("binary save data nested too deep")));
+  }
+
+  // it should skip component types that were not registered for serialization
+  #[test]
+  fn test_unregistered_skipped() {
+    let mut serializer = WorldSerializer::new();
+    serializer.register::<Health>("Health");
+    let saved = serializer.save_yaml(&saved_world());
+    let mut loaded = World::new();
+    let entities = serializer.load_yaml(&mut loaded, &saved).unwrap();
+    assert_eq!(loaded.get::<Health>(entities[0]), Some(&Health{ hp: 10 }));
+    assert_eq!(loaded.resource::<Score>(), None);
+  }
+
+  // it should leave the world untouched when loading fails
+  #[test]
+  fn test_load_errors() {
+    let serializer = serializer();
+    let mut loaded = World::new();
+    let unknown = "entities:\n  - id: 4\n    components:\n      Health: 1\n  - id: 5\n    components:\n      Mana: 2\n";
+    assert_eq!(serializer.load_yaml(&mut loaded, unknown), Err(SerializeError::UnknownType("Mana".into())));
+    let dangling = "entities:\n  - id: 4\n    components:\n      Target: 9\n";
+    assert_eq!(serializer.load_yaml(&mut loaded, dangling), Err(SerializeError::InvalidData("Target".into())));
+    assert_eq!(loaded.iter().count(), 0);
+  }
+
+  // it should not insert resources, run hooks or use up entity generations when loading fails
+  #[test]
+  fn test_load_staged() {
+    let serializer = serializer();
+    let mut loaded = World::new();
+    loaded.insert_resource(Score { points: 1.0, label: "old".into() });
+    let hooks_run = Arc::new(Mutex::new(0));
+    let created_count = hooks_run.clone();
+    loaded.on_entity_created(move |_, _| *created_count.lock().unwrap() += 1);
+    let removed_count = hooks_run.clone();
+    loaded.on_entity_removed(move |_, _| *removed_count.lock().unwrap() += 1);
+    let source = "entities:\n  - id: 4\n    components:\n      Health: 1\nresources:\n  Score: { points: 5.0, label: new }\n  Mana: 2\n";
+    assert_eq!(serializer.load_yaml(&mut loaded, source), Err(SerializeError::UnknownType("Mana".into())));
+    assert_eq!(loaded.resource::<Score>(), Some(&Score { points: 1.0, label: "old".into() }));
+    assert_eq!(*hooks_run.lock().unwrap(), 0);
+    assert_eq!(loaded.create(), Entity(0, 0));
+  }
+
+  // it should keep infinities and NaN in the binary format and report reals it can't parse
+  #[test]
+  fn test_binary_reals() {
+    let mut doc = Hash::new();
+    doc.insert(Yaml::String("entities".into()), Yaml::Array(vec![]));
+    let mut resources = Hash::new();
+    let mut score = Hash::new();
+    score.insert(Yaml::String("points".into()), Yaml::Real(".inf".into()));
+    score.insert(Yaml::String("label".into()), Yaml::String("endless".into()));
+    resources.insert(Yaml::String("Score".into()), Yaml::Hash(score));
+    doc.insert(Yaml::String("resources".into()), Yaml::Hash(resources));
+    let mut bytes = b"SPEW\x01".to_vec();
+    super::encode(&Yaml::Hash(doc), 0, &mut bytes).unwrap();
+    let mut loaded = World::new();
+    serializer().load_binary(&mut loaded, &bytes).unwrap();
+    assert_eq!(loaded.resource::<Score>().map(|score| score.points), Some(f64::INFINITY));
+    assert!(yaml_to_float(&Yaml::Real(".NaN".into())).unwrap().is_nan());
+    assert_eq!(super::encode(&Yaml::Real("lots".into()), 0, &mut Vec::new()),
+      Err(SerializeError::Malformed("real number that can't be parsed")));
+  }
+}
@@ -0,0 +1,381 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+
+use super::{Entity, Component, Resource, Store, ComponentStore, World, WorldState, Storage};
+use super::{Parent, Children, Transform, GlobalTransform, Name};
+use super::tracking::ComponentTicks;
+
+type CloneStore = fn(&Store) -> Box<Store>;
+// builds the store to put back from the saved one and the one it replaces, if any
+type RestoreStore = fn(&Store, Option<&Store>, usize) -> Box<Store>;
+type CloneResource = fn(&Resource) -> Box<Resource>;
+
+fn clone_store<T: Component>(store: &Store) -> Box<Store> where T::Storage: Clone {
+  let typed_store = store.downcast_ref::<ComponentStore<T>>()
+    .expect("Error: Snapshot registered for a different component type");
+  Box::new(typed_store.clone())
+}
+
+// Going back counts as a change made now: restored values are stamped with the current tick,
+// as added if the entity didn't have one before, and values that disappear are recorded as removed
+fn restore_store<T: Component>(saved: &Store, current: Option<&Store>, tick: usize) -> Box<Store> where T::Storage: Clone {
+  let mut restored = saved.downcast_ref::<ComponentStore<T>>()
+    .expect("Error: Snapshot registered for a different component type")
+    .clone();
+  let current = current.map(|store| {
+    store.downcast_ref::<ComponentStore<T>>().expect("Error: Snapshot registered for a different component type")
+  });
+  restored.removed = current.map_or(Vec::new(), |current| current.removed.clone());
+  if let Some(current) = current {
+    for &e in current.data.entities() {
+      if !restored.contains(e) {
+        restored.removed.push((e, tick));
+      }
+    }
+  }
+  for &e in restored.data.entities() {
+    if current.map_or(false, |current| current.contains(e)) {
+      restored.ticks[e.0].changed = tick;
+    } else {
+      restored.ticks[e.0] = ComponentTicks::new(tick);
+    }
+  }
+  Box::new(restored)
+}
+
+// the lowest generation the state could still hand out for id
+fn next_generation(world_state: &WorldState, id: usize) -> usize {
+  if id >= world_state.current_id {
+    return world_state.first_generation;
+  }
+  let generation = world_state.generations[id];
+  let next = if world_state.active.contains(&Entity(id, generation)) { generation.wrapping_add(1) } else { generation };
+  world_state.generation_floors.get(&id).map_or(next, |&floor| floor.max(next))
+}
+
+fn store_fns<T: Component>() -> (CloneStore, RestoreStore) where T::Storage: Clone {
+  (clone_store::<T>, restore_store::<T>)
+}
+
+fn clone_resource<R: Resource + Clone>(res: &Resource) -> Box<Resource> {
+  let typed_res = res.downcast_ref::<R>()
+    .expect("Error: Snapshot registered for a different resource type");
+  Box::new(typed_res.clone())
+}
+
+// The component and resource types that opted into snapshots, with how to clone and restore them
+pub struct SnapshotTypes {
+  components: HashMap<TypeId, (CloneStore, RestoreStore)>,
+  resources: HashMap<TypeId, CloneResource>
+}
+
+impl SnapshotTypes {
+  pub fn new() -> SnapshotTypes {
+    let mut types = SnapshotTypes {
+      components: HashMap::new(),
+      resources: HashMap::new()
+    };
+    types.components.insert(TypeId::of::<Parent>(), store_fns::<Parent>());
+    types.components.insert(TypeId::of::<Children>(), store_fns::<Children>());
+    types.components.insert(TypeId::of::<Transform>(), store_fns::<Transform>());
+    types.components.insert(TypeId::of::<GlobalTransform>(), store_fns::<GlobalTransform>());
+    types.components.insert(TypeId::of::<Name>(), store_fns::<Name>());
+    types
+  }
+
+  pub(super) fn has_component(&self, ty: &TypeId) -> bool {
+    self.components.contains_key(ty)
+  }
+}
+
+// A copy of the current state's entities and of every snapshot-enabled component store and resource
+pub struct WorldSnapshot {
+  state: WorldState
+}
+
+impl WorldSnapshot {
+  pub fn len(&self) -> usize {
+    self.state.active.len()
+  }
+
+  pub fn contains(&self, e: Entity) -> bool {
+    self.state.active.contains(&e)
+  }
+
+  pub(super) fn into_state(self) -> WorldState {
+    self.state
+  }
+}
+
+impl World {
+  // components and resources are left out of snapshots unless registered here
+  pub fn register_snapshot<T: Component>(&mut self) where T::Storage: Clone {
+    self.snapshot_types.components.insert(TypeId::of::<T>(), store_fns::<T>());
+  }
+
+  pub fn register_snapshot_resource<R: Resource + Clone>(&mut self) {
+    self.snapshot_types.resources.insert(TypeId::of::<R>(), clone_resource::<R>);
+  }
+
+  pub fn snapshot(&self) -> WorldSnapshot {
+    let world_state = self.current_state();
+    let types = &self.snapshot_types;
+    let components = world_state.components.iter()
+      .filter_map(|(key, store)| types.components.get(&key.1).map(|&(clone, _)| (*key, clone(&**store))))
+      .collect();
+    let resources = world_state.resources.iter()
+      .filter_map(|(ty, res)| types.resources.get(ty).map(|clone| (*ty, clone(&**res))))
+      .collect();
+    WorldSnapshot {
+      state: WorldState {
+        current_id: world_state.current_id,
+        reusable_ids: world_state.reusable_ids.clone(),
+        generations: world_state.generations.clone(),
+        active: world_state.active.clone(),
+        components: components,
+        resources: resources,
+        inheritance: world_state.inheritance,
+        first_generation: world_state.first_generation,
+        generation_floors: world_state.generation_floors.clone(),
+        inherited: world_state.inherited.clone()
+      }
+    }
+  }
+
+  // Puts the current state back the way it was when the snapshot was taken. Component types
+  // that aren't snapshot-enabled keep their current values, minus those of entities the
+  // snapshot doesn't have. Change tracking sees the difference as added, changed and removed
+  // components. Entities the snapshot lacks stay dead for good: their handles never match the
+  // entities that reuse their ids. A snapshot can be restored any number of times.
+  //
+  // Hooks see the restore as the entities, and the hooked components, that come and go: the
+  // entities the snapshot lacks are removed first, then hooked components the snapshot doesn't
+  // have are taken off the rest, and once everything is back the entities the snapshot brings
+  // back are created and the hooked components it puts back on are added. Components that are
+  // there before and after only change in place, which runs no hooks.
+  pub fn restore(&mut self, snapshot: &WorldSnapshot) {
+    let saved = &snapshot.state;
+    let (discarded, kept, revived) = {
+      let active = &self.current_state().active;
+      let mut discarded: Vec<Entity> = active.difference(&saved.active).cloned().collect();
+      let mut kept: Vec<Entity> = active.intersection(&saved.active).cloned().collect();
+      let mut revived: Vec<Entity> = saved.active.difference(active).cloned().collect();
+      discarded.sort();
+      kept.sort();
+      revived.sort();
+      (discarded, kept, revived)
+    };
+    // None when the snapshot has no store for the type, which the restore then leaves alone
+    let saved_has = |ty: TypeId, e: Entity| saved.components.get(&((), ty)).map(|comp_store| comp_store.has(e));
+    for &e in &discarded {
+      self.despawn(e).expect("Error: Could not find ecs world state (mut)");
+    }
+    for &e in &kept {
+      self.take_hooked_where(e, |ty| saved_has(ty, e) == Some(false));
+    }
+    let mut added = Vec::new();
+    {
+      let hooked = self.hooked_additions();
+      let world_state = self.current_state();
+      for &e in kept.iter().chain(&revived) {
+        for &ty in &hooked {
+          let has_now = world_state.components.get(&((), ty)).map_or(false, |comp_store| comp_store.has(e));
+          if saved_has(ty, e) == Some(true) && !has_now {
+            added.push((e, ty));
+          }
+        }
+      }
+    }
+    self.restore_state(saved);
+    for &e in &revived {
+      self.run_entity_created(e);
+    }
+    for &(e, ty) in &added {
+      self.run_added_where(e, |hooked| hooked == ty);
+    }
+  }
+
+  fn restore_state(&mut self, saved: &WorldState) {
+    let tick = self.change_tick;
+    let World { ref mut world_state_stack, ref snapshot_types, .. } = *self;
+    let world_state = world_state_stack.last_mut().expect("Error: Could not find ecs world state (mut)");
+    for (key, comp_store) in &saved.components {
+      let (_, restore) = snapshot_types.components[&key.1];
+      let restored = restore(&**comp_store, world_state.components.get(key).map(|current| &**current), tick);
+      world_state.components.insert(*key, restored);
+    }
+    world_state.resources.retain(|ty, _| !snapshot_types.resources.contains_key(ty));
+    for (ty, res) in &saved.resources {
+      let clone = snapshot_types.resources[ty];
+      world_state.resources.insert(*ty, clone(&**res));
+    }
+    // generations only move forward, except for the snapshot's own entities coming back
+    let current_id = world_state.current_id.max(saved.current_id);
+    let mut generations = Vec::with_capacity(current_id);
+    let mut floors = HashMap::new();
+    for id in 0..current_id {
+      let next = next_generation(world_state, id).max(next_generation(saved, id));
+      let revived = id < saved.current_id && saved.active.contains(&Entity(id, saved.generations[id]));
+      if revived {
+        generations.push(saved.generations[id]);
+        if next > saved.generations[id].wrapping_add(1) {
+          floors.insert(id, next);
+        }
+      } else {
+        generations.push(next);
+      }
+    }
+    // the ids the snapshot would have handed out next still come first
+    let mut reusable_ids: Vec<usize> = (saved.current_id..current_id).rev().collect();
+    reusable_ids.extend(saved.reusable_ids.iter().cloned());
+    world_state.current_id = current_id;
+    world_state.reusable_ids = reusable_ids;
+    world_state.generations = generations;
+    world_state.generation_floors = floors;
+    world_state.active = saved.active.clone();
+  }
+}
+
+#[cfg(test)]
+mod snapshot_tests {
+  use super::super::*;
+
+  // describe: snapshots of the current world state
+
+  #[derive(Clone, PartialEq, Debug)]
+  struct Health {
+    hp: usize
+  }
+
+  impl Component for Health {
+    type Storage = VecStorage<Self>;
+  }
+
+  // not snapshot-enabled
+  struct Sprite;
+
+  impl Component for Sprite {
+    type Storage = NullStorage<Self>;
+  }
+
+  #[derive(Clone, PartialEq, Debug)]
+  struct Turn(usize);
+
+  fn snapshot_world() -> World {
+    let mut test_world = World::new();
+    test_world.register_comp::<Sprite>();
+    test_world.register_snapshot::<Health>();
+    test_world.register_snapshot_resource::<Turn>();
+    test_world
+  }
+
+  // it should roll component values, entities and resources back
+  #[test]
+  fn test_restore() {
+    let mut test_world = snapshot_world();
+    let hero = test_world.spawn_bundle((Health{ hp: 10 }, Transform::at(1.0, 0.0)));
+    let doomed = test_world.spawn_bundle((Health{ hp: 1 },));
+    test_world.insert_resource(Turn(1));
+    let snapshot = test_world.snapshot();
+    test_world.get_mut::<Health>(hero).unwrap().hp = 4;
+    test_world.get_mut::<Transform>(hero).unwrap().translation = [5.0, 5.0];
+    test_world.remove(doomed);
+    let newcomer = test_world.spawn_bundle((Health{ hp: 7 },));
+    test_world.insert_resource(Turn(2));
+    test_world.restore(&snapshot);
+    assert_eq!(test_world.get::<Health>(hero), Some(&Health{ hp: 10 }));
+    assert_eq!(test_world.get::<Transform>(hero).map(|transform| transform.translation), Some([1.0, 0.0]));
+    assert_eq!(test_world.get::<Health>(doomed), Some(&Health{ hp: 1 }));
+    assert_eq!(test_world.contains(newcomer), false);
+    assert_eq!(test_world.resource::<Turn>(), Some(&Turn(1)));
+    assert_eq!(snapshot.len(), 2);
+    // handles to entities the restore threw away stay dead
+    let created = test_world.create();
+    assert!(created != newcomer && created != doomed);
+    assert_eq!(test_world.contains(newcomer), false);
+  }
+
+  // it should never bring a discarded handle back, even through the entities it revives
+  #[test]
+  fn test_restore_generations() {
+    let mut test_world = snapshot_world();
+    let hero = test_world.create();
+    let snapshot = test_world.snapshot();
+    test_world.remove(hero);
+    let usurper = test_world.create();
+    assert_eq!(usurper.id(), hero.id());
+    test_world.restore(&snapshot);
+    assert_eq!((test_world.contains(hero), test_world.contains(usurper)), (true, false));
+    test_world.remove(hero);
+    let heir = test_world.create();
+    assert_eq!(heir.id(), hero.id());
+    assert!(heir != hero && heir != usurper);
+    assert_eq!(test_world.contains(usurper), false);
+  }
+
+  // it should keep components that did not opt in, except on entities the snapshot lacks
+  #[test]
+  fn test_unregistered_components() {
+    let mut test_world = snapshot_world();
+    let hero = test_world.spawn_bundle((Health{ hp: 10 },));
+    let snapshot = test_world.snapshot();
+    test_world.add_comp(hero, Sprite);
+    let newcomer = test_world.spawn_bundle((Sprite,));
+    test_world.restore(&snapshot);
+    assert_eq!(test_world.has::<Sprite>(hero), true);
+    assert_eq!(test_world.get_comp::<Sprite>().len(), 1);
+    assert_eq!(snapshot.contains(newcomer), false);
+  }
+
+  // it should report what the restore changed to change tracking
+  #[test]
+  fn test_restore_tracking() {
+    let mut test_world = snapshot_world();
+    let hero = test_world.spawn_bundle((Health{ hp: 10 },));
+    let doomed = test_world.spawn_bundle((Health{ hp: 1 },));
+    let snapshot = test_world.snapshot();
+    test_world.remove(doomed);
+    let newcomer = test_world.spawn_bundle((Health{ hp: 7 },));
+    test_world.clear_trackers();
+    test_world.restore(&snapshot);
+    assert_eq!(test_world.is_changed::<Health>(hero), true);
+    assert_eq!(test_world.is_added::<Health>(hero), false);
+    assert_eq!(test_world.is_added::<Health>(doomed), true);
+    assert_eq!(test_world.removed::<Health>().collect::<Vec<_>>(), vec![newcomer]);
+  }
+
+  // it should run the hooks for the entities and hooked components that come and go
+  #[test]
+  fn test_restore_hooks() {
+    struct Bodies(Vec<Entity>);
+    let mut test_world = snapshot_world();
+    test_world.insert_resource(Bodies(Vec::new()));
+    test_world.on_add::<Health, _>(|world, e| world.resource_mut::<Bodies>().unwrap().0.push(e));
+    test_world.on_remove::<Health, _>(|world, e, _| world.resource_mut::<Bodies>().unwrap().0.retain(|&body| body != e));
+    let hero = test_world.spawn_bundle((Health{ hp: 10 },));
+    let doomed = test_world.spawn_bundle((Health{ hp: 1 },));
+    let snapshot = test_world.snapshot();
+    test_world.remove(doomed);
+    test_world.remove_comp::<Health>(hero);
+    test_world.spawn_bundle((Health{ hp: 7 },));
+    test_world.restore(&snapshot);
+    assert_eq!(test_world.resource::<Bodies>().unwrap().0, vec![hero, doomed]);
+    let bystander = test_world.spawn_bundle((Health{ hp: 3 },));
+    test_world.restore(&snapshot);
+    assert_eq!(test_world.contains(bystander), false);
+    assert_eq!(test_world.resource::<Bodies>().unwrap().0, vec![hero, doomed]);
+  }
+
+  // it should be able to restore the same snapshot more than once
+  #[test]
+  fn test_restore_twice() {
+    let mut test_world = snapshot_world();
+    let hero = test_world.spawn_bundle((Health{ hp: 10 },));
+    let snapshot = test_world.snapshot();
+    for _ in 0..2 {
+      test_world.get_mut::<Health>(hero).unwrap().hp -= 5;
+      test_world.restore(&snapshot);
+      assert_eq!(test_world.get::<Health>(hero), Some(&Health{ hp: 10 }));
+    }
+  }
+}
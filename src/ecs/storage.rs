@@ -0,0 +1,548 @@
+use std::collections::HashMap;
+use std::iter::Zip;
+use std::mem;
+use std::slice;
+use mopa::Any;
+
+use super::Entity;
+
+// Backing container for a single component type; picked per component through Component::Storage
+pub trait Storage<T>: Any + Send + Sync + Sized {
+  type Iter<'a>: Iterator<Item = (&'a Entity, &'a T)> where Self: 'a, T: 'a;
+  type IterMut<'a>: Iterator<Item = (&'a Entity, &'a mut T)> where Self: 'a, T: 'a;
+
+  fn new() -> Self;
+  fn insert(&mut self, e: Entity, comp: T) -> Option<T>;
+  fn get(&self, e: Entity) -> Option<&T>;
+  fn get_mut(&mut self, e: Entity) -> Option<&mut T>;
+  fn remove(&mut self, e: Entity) -> Option<T>;
+  fn iter<'a>(&'a self) -> Self::Iter<'a>;
+  fn iter_mut<'a>(&'a mut self) -> Self::IterMut<'a>;
+  // every entity in the storage, packed together so queries can walk the smallest store they need
+  fn entities(&self) -> &[Entity];
+  // Like get_mut, for queries that hand out several components at once: the pointer must be
+  // reached without creating a reference to any other component, or the ones already handed out
+  // would be invalidated. this must be valid for writes.
+  unsafe fn get_ptr(this: *mut Self, e: Entity) -> Option<*mut T>;
+
+  fn len(&self) -> usize {
+    self.entities().len()
+  }
+
+  // queries check this next to components they already handed out, so it shouldn't read any of them
+  fn contains(&self, e: Entity) -> bool;
+}
+
+pub struct StorageIter<'a, T: 'a, S: Storage<T> + 'a> {
+  inner: S::Iter<'a>,
+  len: usize
+}
+
+impl<'a, T: 'a, S: Storage<T>> StorageIter<'a, T, S> {
+  pub fn new(storage: &'a S) -> StorageIter<'a, T, S> {
+    StorageIter {
+      inner: storage.iter(),
+      len: storage.len()
+    }
+  }
+}
+
+impl<'a, T: 'a, S: Storage<T>> Iterator for StorageIter<'a, T, S> {
+  type Item = (&'a Entity, &'a T);
+
+  fn next(&mut self) -> Option<(&'a Entity, &'a T)> {
+    let next = self.inner.next();
+    if next.is_some() {
+      self.len -= 1;
+    }
+    next
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.len, Some(self.len))
+  }
+}
+
+impl<'a, T: 'a, S: Storage<T>> ExactSizeIterator for StorageIter<'a, T, S> {}
+
+pub struct StorageIterMut<'a, T: 'a, S: Storage<T> + 'a> {
+  inner: S::IterMut<'a>,
+  len: usize
+}
+
+impl<'a, T: 'a, S: Storage<T>> StorageIterMut<'a, T, S> {
+  pub fn new(storage: &'a mut S) -> StorageIterMut<'a, T, S> {
+    let len = storage.len();
+    StorageIterMut {
+      inner: storage.iter_mut(),
+      len: len
+    }
+  }
+}
+
+impl<'a, T: 'a, S: Storage<T>> Iterator for StorageIterMut<'a, T, S> {
+  type Item = (&'a Entity, &'a mut T);
+
+  fn next(&mut self) -> Option<(&'a Entity, &'a mut T)> {
+    let next = self.inner.next();
+    if next.is_some() {
+      self.len -= 1;
+    }
+    next
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.len, Some(self.len))
+  }
+}
+
+impl<'a, T: 'a, S: Storage<T>> ExactSizeIterator for StorageIterMut<'a, T, S> {}
+
+// Good default for components only a few entities have
+#[derive(Clone)]
+pub struct HashMapStorage<T> {
+  index: HashMap<Entity, usize>,
+  dense: Vec<Entity>,
+  data: Vec<T>
+}
+
+impl<T: Any + Send + Sync> Storage<T> for HashMapStorage<T> {
+  type Iter<'a> = Zip<slice::Iter<'a, Entity>, slice::Iter<'a, T>> where T: 'a;
+  type IterMut<'a> = Zip<slice::Iter<'a, Entity>, slice::IterMut<'a, T>> where T: 'a;
+
+  fn new() -> HashMapStorage<T> {
+    HashMapStorage {
+      index: HashMap::new(),
+      dense: Vec::new(),
+      data: Vec::new()
+    }
+  }
+
+  fn insert(&mut self, e: Entity, comp: T) -> Option<T> {
+    if let Some(&index) = self.index.get(&e) {
+      return Some(mem::replace(&mut self.data[index], comp));
+    }
+    self.index.insert(e, self.dense.len());
+    self.dense.push(e);
+    self.data.push(comp);
+    None
+  }
+
+  fn get(&self, e: Entity) -> Option<&T> {
+    self.index.get(&e).map(|&index| &self.data[index])
+  }
+
+  fn get_mut(&mut self, e: Entity) -> Option<&mut T> {
+    match self.index.get(&e) {
+      Some(&index) => Some(&mut self.data[index]),
+      None => None
+    }
+  }
+
+  fn remove(&mut self, e: Entity) -> Option<T> {
+    let index = match self.index.remove(&e) {
+      Some(index) => index,
+      None => return None
+    };
+    self.dense.swap_remove(index);
+    if let Some(&moved) = self.dense.get(index) {
+      self.index.insert(moved, index);
+    }
+    Some(self.data.swap_remove(index))
+  }
+
+  fn iter<'a>(&'a self) -> Self::Iter<'a> {
+    self.dense.iter().zip(self.data.iter())
+  }
+
+  fn iter_mut<'a>(&'a mut self) -> Self::IterMut<'a> {
+    self.dense.iter().zip(self.data.iter_mut())
+  }
+
+  fn entities(&self) -> &[Entity] {
+    &self.dense
+  }
+
+  unsafe fn get_ptr(this: *mut Self, e: Entity) -> Option<*mut T> {
+    let index = match (*this).index.get(&e) {
+      Some(&index) => index,
+      None => return None
+    };
+    Some((*this).data.as_mut_ptr().add(index))
+  }
+
+  fn contains(&self, e: Entity) -> bool {
+    self.index.contains_key(&e)
+  }
+}
+
+// Indexed directly by entity id; best for components that almost every entity has
+#[derive(Clone)]
+pub struct VecStorage<T> {
+  // each slot keeps the entity it belongs to and where that entity sits in dense
+  data: Vec<Option<(Entity, usize, T)>>,
+  dense: Vec<Entity>
+}
+
+pub struct VecIter<'a, T: 'a> {
+  slots: slice::Iter<'a, Option<(Entity, usize, T)>>
+}
+
+impl<'a, T> Iterator for VecIter<'a, T> {
+  type Item = (&'a Entity, &'a T);
+
+  fn next(&mut self) -> Option<(&'a Entity, &'a T)> {
+    while let Some(slot) = self.slots.next() {
+      if let Some((ref e, _, ref comp)) = *slot {
+        return Some((e, comp));
+      }
+    }
+    None
+  }
+}
+
+pub struct VecIterMut<'a, T: 'a> {
+  slots: slice::IterMut<'a, Option<(Entity, usize, T)>>
+}
+
+impl<'a, T> Iterator for VecIterMut<'a, T> {
+  type Item = (&'a Entity, &'a mut T);
+
+  fn next(&mut self) -> Option<(&'a Entity, &'a mut T)> {
+    while let Some(slot) = self.slots.next() {
+      if let Some((ref e, _, ref mut comp)) = *slot {
+        return Some((&*e, comp));
+      }
+    }
+    None
+  }
+}
+
+impl<T: Any + Send + Sync> Storage<T> for VecStorage<T> {
+  type Iter<'a> = VecIter<'a, T> where T: 'a;
+  type IterMut<'a> = VecIterMut<'a, T> where T: 'a;
+
+  fn new() -> VecStorage<T> {
+    VecStorage {
+      data: Vec::new(),
+      dense: Vec::new()
+    }
+  }
+
+  fn insert(&mut self, e: Entity, comp: T) -> Option<T> {
+    while self.data.len() <= e.0 {
+      self.data.push(None);
+    }
+    let old = self.data[e.0].take();
+    match old {
+      Some((old_e, index, old_comp)) => {
+        // a slot still holding an older generation of this id is overwritten
+        self.dense[index] = e;
+        self.data[e.0] = Some((e, index, comp));
+        if old_e == e {
+          Some(old_comp)
+        } else {
+          None
+        }
+      },
+      None => {
+        self.data[e.0] = Some((e, self.dense.len(), comp));
+        self.dense.push(e);
+        None
+      }
+    }
+  }
+
+  fn get(&self, e: Entity) -> Option<&T> {
+    match self.data.get(e.0) {
+      Some(&Some((ref slot_e, _, ref comp))) if *slot_e == e => Some(comp),
+      _ => None
+    }
+  }
+
+  fn get_mut(&mut self, e: Entity) -> Option<&mut T> {
+    match self.data.get_mut(e.0) {
+      Some(&mut Some((ref slot_e, _, ref mut comp))) if *slot_e == e => Some(comp),
+      _ => None
+    }
+  }
+
+  fn remove(&mut self, e: Entity) -> Option<T> {
+    if !self.contains(e) {
+      return None;
+    }
+    let (_, index, comp) = self.data[e.0].take().expect("Error: VecStorage slot is empty");
+    self.dense.swap_remove(index);
+    if let Some(&moved) = self.dense.get(index) {
+      if let Some((_, ref mut moved_index, _)) = self.data[moved.0] {
+        *moved_index = index;
+      }
+    }
+    Some(comp)
+  }
+
+  fn iter<'a>(&'a self) -> Self::Iter<'a> {
+    VecIter { slots: self.data.iter() }
+  }
+
+  fn iter_mut<'a>(&'a mut self) -> Self::IterMut<'a> {
+    VecIterMut { slots: self.data.iter_mut() }
+  }
+
+  fn entities(&self) -> &[Entity] {
+    &self.dense
+  }
+
+  unsafe fn get_ptr(this: *mut Self, e: Entity) -> Option<*mut T> {
+    if e.0 >= (*this).data.len() {
+      return None;
+    }
+    match *(*this).data.as_mut_ptr().add(e.0) {
+      Some((slot_e, _, ref mut comp)) if slot_e == e => Some(comp as *mut T),
+      _ => None
+    }
+  }
+
+  // looks at e's slot alone instead of going through a slice of all of them
+  fn contains(&self, e: Entity) -> bool {
+    if e.0 >= self.data.len() {
+      return false;
+    }
+    match unsafe { &*self.data.as_ptr().add(e.0) } {
+      &Some((slot_e, _, _)) => slot_e == e,
+      &None => false
+    }
+  }
+}
+
+// Packed array with an id lookup table; iterates as fast as a Vec without leaving holes
+#[derive(Clone)]
+pub struct SparseSetStorage<T> {
+  sparse: Vec<Option<usize>>,
+  dense: Vec<Entity>,
+  data: Vec<T>
+}
+
+impl<T> SparseSetStorage<T> {
+  fn index(&self, e: Entity) -> Option<usize> {
+    match self.sparse.get(e.0) {
+      Some(&Some(index)) if self.dense[index] == e => Some(index),
+      _ => None
+    }
+  }
+}
+
+impl<T: Any + Send + Sync> Storage<T> for SparseSetStorage<T> {
+  type Iter<'a> = Zip<slice::Iter<'a, Entity>, slice::Iter<'a, T>> where T: 'a;
+  type IterMut<'a> = Zip<slice::Iter<'a, Entity>, slice::IterMut<'a, T>> where T: 'a;
+
+  fn new() -> SparseSetStorage<T> {
+    SparseSetStorage {
+      sparse: Vec::new(),
+      dense: Vec::new(),
+      data: Vec::new()
+    }
+  }
+
+  fn insert(&mut self, e: Entity, comp: T) -> Option<T> {
+    if let Some(index) = self.index(e) {
+      return Some(mem::replace(&mut self.data[index], comp));
+    }
+    // a slot still holding an older generation of this id is overwritten
+    let stale = self.sparse.get(e.0).and_then(|slot| slot.map(|index| self.dense[index]));
+    if let Some(stale) = stale {
+      self.remove(stale);
+    }
+    while self.sparse.len() <= e.0 {
+      self.sparse.push(None);
+    }
+    self.sparse[e.0] = Some(self.dense.len());
+    self.dense.push(e);
+    self.data.push(comp);
+    None
+  }
+
+  fn get(&self, e: Entity) -> Option<&T> {
+    self.index(e).map(|index| &self.data[index])
+  }
+
+  fn get_mut(&mut self, e: Entity) -> Option<&mut T> {
+    match self.index(e) {
+      Some(index) => Some(&mut self.data[index]),
+      None => None
+    }
+  }
+
+  fn remove(&mut self, e: Entity) -> Option<T> {
+    let index = match self.index(e) {
+      Some(index) => index,
+      None => return None
+    };
+    self.sparse[e.0] = None;
+    self.dense.swap_remove(index);
+    if let Some(moved) = self.dense.get(index) {
+      self.sparse[moved.0] = Some(index);
+    }
+    Some(self.data.swap_remove(index))
+  }
+
+  fn iter<'a>(&'a self) -> Self::Iter<'a> {
+    self.dense.iter().zip(self.data.iter())
+  }
+
+  fn iter_mut<'a>(&'a mut self) -> Self::IterMut<'a> {
+    self.dense.iter().zip(self.data.iter_mut())
+  }
+
+  fn entities(&self) -> &[Entity] {
+    &self.dense
+  }
+
+  unsafe fn get_ptr(this: *mut Self, e: Entity) -> Option<*mut T> {
+    match (*this).index(e) {
+      Some(index) => Some((*this).data.as_mut_ptr().add(index)),
+      None => None
+    }
+  }
+
+  fn contains(&self, e: Entity) -> bool {
+    self.index(e).is_some()
+  }
+}
+
+// For zero-sized tag components; only the set of entities takes up memory
+#[derive(Clone)]
+pub struct NullStorage<T> {
+  set: SparseSetStorage<T>
+}
+
+impl<T: Any + Send + Sync> Storage<T> for NullStorage<T> {
+  type Iter<'a> = <SparseSetStorage<T> as Storage<T>>::Iter<'a> where T: 'a;
+  type IterMut<'a> = <SparseSetStorage<T> as Storage<T>>::IterMut<'a> where T: 'a;
+
+  fn new() -> NullStorage<T> {
+    if mem::size_of::<T>() != 0 {
+      panic!("Error: NullStorage can only be used for zero-sized components")
+    }
+    NullStorage { set: SparseSetStorage::new() }
+  }
+
+  fn insert(&mut self, e: Entity, comp: T) -> Option<T> {
+    self.set.insert(e, comp)
+  }
+
+  fn get(&self, e: Entity) -> Option<&T> {
+    self.set.get(e)
+  }
+
+  fn get_mut(&mut self, e: Entity) -> Option<&mut T> {
+    self.set.get_mut(e)
+  }
+
+  fn remove(&mut self, e: Entity) -> Option<T> {
+    self.set.remove(e)
+  }
+
+  fn iter<'a>(&'a self) -> Self::Iter<'a> {
+    self.set.iter()
+  }
+
+  fn iter_mut<'a>(&'a mut self) -> Self::IterMut<'a> {
+    self.set.iter_mut()
+  }
+
+  fn entities(&self) -> &[Entity] {
+    self.set.entities()
+  }
+
+  unsafe fn get_ptr(this: *mut Self, e: Entity) -> Option<*mut T> {
+    SparseSetStorage::get_ptr(&mut (*this).set, e)
+  }
+
+  fn contains(&self, e: Entity) -> bool {
+    self.set.contains(e)
+  }
+}
+
+#[cfg(test)]
+mod storage_tests {
+  use super::*;
+
+  // describe: a component storage backend
+
+  struct TestComponent {
+    x: usize
+  }
+
+  fn check_storage<S: Storage<TestComponent>>() {
+    let mut storage = S::new();
+    let entity0 = Entity(0, 0);
+    let entity1 = Entity(1, 0);
+    let entity2 = Entity(2, 0);
+    assert_eq!(storage.insert(entity0, TestComponent{ x: 1 }).is_none(), true);
+    assert_eq!(storage.insert(entity1, TestComponent{ x: 2 }).is_none(), true);
+    assert_eq!(storage.insert(entity2, TestComponent{ x: 3 }).is_none(), true);
+    assert_eq!(storage.len(), 3);
+    assert_eq!(storage.insert(entity1, TestComponent{ x: 4 }).map(|comp| comp.x), Some(2));
+    assert_eq!(storage.len(), 3);
+    assert_eq!(storage.get(entity1).map(|comp| comp.x), Some(4));
+    if let Some(comp) = storage.get_mut(entity2) {
+      comp.x += 10;
+    }
+    assert_eq!(storage.get(entity2).map(|comp| comp.x), Some(13));
+    assert_eq!(storage.remove(entity0).map(|comp| comp.x), Some(1));
+    assert_eq!(storage.remove(entity0).is_none(), true);
+    assert_eq!(storage.contains(entity0), false);
+    assert_eq!(storage.len(), 2);
+    assert_eq!(storage.get(Entity(1, 1)).is_none(), true);
+    for (_, comp) in storage.iter_mut() {
+      comp.x += 1;
+    }
+    let mut remaining: Vec<_> = storage.iter().map(|(e, comp)| (*e, comp.x)).collect();
+    remaining.sort();
+    assert_eq!(remaining, vec![(entity1, 5), (entity2, 14)]);
+    assert_eq!(StorageIter::new(&storage).len(), 2);
+    let mut entities = storage.entities().to_vec();
+    entities.sort();
+    assert_eq!(entities, vec![entity1, entity2]);
+  }
+
+  // it should store, replace and remove components in a HashMapStorage
+  #[test]
+  fn test_hash_map_storage() {
+    check_storage::<HashMapStorage<TestComponent>>();
+  }
+
+  // it should store, replace and remove components in a VecStorage
+  #[test]
+  fn test_vec_storage() {
+    check_storage::<VecStorage<TestComponent>>();
+  }
+
+  // it should store, replace and remove components in a SparseSetStorage
+  #[test]
+  fn test_sparse_set_storage() {
+    check_storage::<SparseSetStorage<TestComponent>>();
+  }
+
+  struct TestTag;
+
+  // it should track tagged entities in a NullStorage
+  #[test]
+  fn test_null_storage() {
+    let mut storage = NullStorage::<TestTag>::new();
+    storage.insert(Entity(3, 0), TestTag);
+    storage.insert(Entity(7, 0), TestTag);
+    storage.remove(Entity(3, 0));
+    assert_eq!(storage.contains(Entity(7, 0)), true);
+    assert_eq!(storage.contains(Entity(3, 0)), false);
+    assert_eq!(storage.len(), 1);
+  }
+
+  // it should panic when a sized component is put in a NullStorage
+  #[test]
+  #[should_panic(expected = "Error: NullStorage can only be used for zero-sized components")]
+  fn test_null_storage_sized() {
+    NullStorage::<TestComponent>::new();
+  }
+}
@@ -0,0 +1,265 @@
+use std::any::TypeId;
+
+use super::World;
+
+pub trait System {
+  // component types the system only reads
+  fn reads(&self) -> Vec<TypeId> {
+    Vec::new()
+  }
+
+  // component types the system mutates
+  fn writes(&self) -> Vec<TypeId> {
+    Vec::new()
+  }
+
+  fn run(&mut self, world: &mut World);
+}
+
+// Every system in an earlier stage finishes before any system in a later one starts
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Stage {
+  PreUpdate,
+  Update,
+  PostUpdate
+}
+
+struct SystemEntry {
+  name: String,
+  stage: Stage,
+  system: Box<System>,
+  // change tick of the system's previous run, so it sees every change made since then
+  last_run: usize
+}
+
+pub struct Dispatcher {
+  systems: Vec<SystemEntry>,
+  // (first, then) pairs of system names
+  orderings: Vec<(String, String)>,
+  schedule: Vec<usize>,
+  dirty: bool
+}
+
+impl Dispatcher {
+  pub fn new() -> Dispatcher {
+    Dispatcher {
+      systems: Vec::new(),
+      orderings: Vec::new(),
+      schedule: Vec::new(),
+      dirty: false
+    }
+  }
+
+  pub fn add_system<S: System + 'static>(&mut self, name: &str, system: S) {
+    self.add_system_to_stage(Stage::Update, name, system);
+  }
+
+  pub fn add_system_to_stage<S: System + 'static>(&mut self, stage: Stage, name: &str, system: S) {
+    if self.systems.iter().any(|entry| entry.name == name) {
+      panic!("Error: A system named {} was already added to the dispatcher", name)
+    }
+    self.systems.push(SystemEntry {
+      name: name.into(),
+      stage: stage,
+      system: Box::new(system),
+      last_run: 0
+    });
+    self.dirty = true;
+  }
+
+  // run the system called first before the system called then
+  pub fn run_before(&mut self, first: &str, then: &str) {
+    self.orderings.push((first.into(), then.into()));
+    self.dirty = true;
+  }
+
+  pub fn run_after(&mut self, then: &str, first: &str) {
+    self.run_before(first, then);
+  }
+
+  pub fn run(&mut self, world: &mut World) {
+    if self.dirty {
+      self.schedule = self.build_schedule();
+      self.dirty = false;
+    }
+    for &index in &self.schedule {
+      let entry = &mut self.systems[index];
+      let previous = world.begin_system(entry.last_run);
+      entry.system.run(world);
+      entry.last_run = world.end_system(previous);
+    }
+  }
+
+  fn index_of(&self, name: &str) -> usize {
+    self.systems.iter().position(|entry| entry.name == name)
+      .unwrap_or_else(|| panic!("Error: Ordering constraint refers to unknown system {}", name))
+  }
+
+  // stage order first, then the ordering constraints, then insertion order
+  fn build_schedule(&self) -> Vec<usize> {
+    let constraints: Vec<(usize, usize)> = self.orderings.iter()
+      .map(|&(ref first, ref then)| (self.index_of(first), self.index_of(then)))
+      .collect();
+    let mut placed = vec![false; self.systems.len()];
+    let mut schedule = Vec::with_capacity(self.systems.len());
+    while schedule.len() < self.systems.len() {
+      let next = (0..self.systems.len())
+        .filter(|&i| !placed[i])
+        .filter(|&i| constraints.iter().all(|&(first, then)| then != i || placed[first]))
+        .min_by_key(|&i| (self.systems[i].stage, i))
+        .expect("Error: Cyclic ordering constraints between systems");
+      let earliest_stage = (0..self.systems.len())
+        .filter(|&i| !placed[i])
+        .map(|i| self.systems[i].stage)
+        .min();
+      if Some(self.systems[next].stage) != earliest_stage {
+        panic!("Error: System ordering constraint conflicts with system stages")
+      }
+      placed[next] = true;
+      schedule.push(next);
+    }
+    schedule
+  }
+}
+
+#[cfg(test)]
+mod system_tests {
+  use std::rc::Rc;
+  use std::cell::RefCell;
+  use super::super::*;
+
+  // describe: a system dispatcher
+
+  struct LogSystem {
+    name: &'static str,
+    log: Rc<RefCell<Vec<&'static str>>>
+  }
+
+  impl System for LogSystem {
+    fn run(&mut self, _world: &mut World) {
+      self.log.borrow_mut().push(self.name);
+    }
+  }
+
+  fn log_system(name: &'static str, log: &Rc<RefCell<Vec<&'static str>>>) -> LogSystem {
+    LogSystem { name: name, log: log.clone() }
+  }
+
+  // it should run systems in the order they were added when unconstrained
+  #[test]
+  fn test_insertion_order() {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add_system("a", log_system("a", &log));
+    dispatcher.add_system("b", log_system("b", &log));
+    dispatcher.run(&mut World::new());
+    assert_eq!(*log.borrow(), vec!["a", "b"]);
+  }
+
+  // it should respect before and after constraints
+  #[test]
+  fn test_ordering() {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add_system("physics", log_system("physics", &log));
+    dispatcher.add_system("ai", log_system("ai", &log));
+    dispatcher.add_system("input", log_system("input", &log));
+    dispatcher.run_before("input", "physics");
+    dispatcher.run_after("ai", "physics");
+    dispatcher.run(&mut World::new());
+    assert_eq!(*log.borrow(), vec!["input", "physics", "ai"]);
+  }
+
+  // it should run every system in an earlier stage first
+  #[test]
+  fn test_stages() {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add_system_to_stage(Stage::PostUpdate, "cleanup", log_system("cleanup", &log));
+    dispatcher.add_system("movement", log_system("movement", &log));
+    dispatcher.add_system_to_stage(Stage::PreUpdate, "input", log_system("input", &log));
+    dispatcher.run(&mut World::new());
+    dispatcher.run(&mut World::new());
+    assert_eq!(*log.borrow(), vec!["input", "movement", "cleanup", "input", "movement", "cleanup"]);
+  }
+
+  struct Counter(usize);
+
+  impl Component for Counter {
+    type Storage = HashMapStorage<Self>;
+  }
+
+  struct Increment;
+
+  impl System for Increment {
+    fn run(&mut self, world: &mut World) {
+      for (_, counter) in world.get_comp_mut::<Counter>() {
+        counter.0 += 1;
+      }
+    }
+  }
+
+  struct WatchChanges {
+    seen: Rc<RefCell<Vec<usize>>>
+  }
+
+  impl System for WatchChanges {
+    fn run(&mut self, world: &mut World) {
+      let changed = world.query::<Changed<Counter>>().count();
+      self.seen.borrow_mut().push(changed);
+    }
+  }
+
+  // it should let each system see changes made since its own previous run, whatever the order
+  #[test]
+  fn test_change_detection() {
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let mut test_world = World::new();
+    test_world.spawn_bundle((Counter(0),));
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add_system("watch", WatchChanges { seen: seen.clone() });
+    dispatcher.add_system("increment", Increment);
+    dispatcher.run(&mut test_world);
+    test_world.clear_trackers();
+    dispatcher.run(&mut test_world);
+    test_world.clear_trackers();
+    dispatcher.run(&mut test_world);
+    assert_eq!(*seen.borrow(), vec![1, 1, 1]);
+  }
+
+  // it should panic when ordering constraints form a cycle
+  #[test]
+  #[should_panic(expected = "Error: Cyclic ordering constraints between systems")]
+  fn test_cycle() {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add_system("a", log_system("a", &log));
+    dispatcher.add_system("b", log_system("b", &log));
+    dispatcher.run_before("a", "b");
+    dispatcher.run_before("b", "a");
+    dispatcher.run(&mut World::new());
+  }
+
+  // it should panic when an ordering constraint contradicts the stages
+  #[test]
+  #[should_panic(expected = "Error: System ordering constraint conflicts with system stages")]
+  fn test_stage_conflict() {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add_system_to_stage(Stage::PreUpdate, "a", log_system("a", &log));
+    dispatcher.add_system_to_stage(Stage::PostUpdate, "b", log_system("b", &log));
+    dispatcher.run_before("b", "a");
+    dispatcher.run(&mut World::new());
+  }
+
+  // it should panic when an ordering constraint names a missing system
+  #[test]
+  #[should_panic(expected = "Error: Ordering constraint refers to unknown system c")]
+  fn test_unknown_system() {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add_system("a", log_system("a", &log));
+    dispatcher.run_before("a", "c");
+    dispatcher.run(&mut World::new());
+  }
+}
@@ -0,0 +1,54 @@
+use std::slice;
+
+use super::Entity;
+
+// Every mutation is stamped with the world's current change tick; a reader sees changes stamped
+// after its own last_change_tick. Outside of a dispatcher that is the last World::clear_trackers,
+// while systems each remember the tick of their own previous run.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ChangeTicks {
+  pub change_tick: usize,
+  pub last_change_tick: usize
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ComponentTicks {
+  pub added: usize,
+  pub changed: usize
+}
+
+impl ComponentTicks {
+  pub fn new(tick: usize) -> ComponentTicks {
+    ComponentTicks {
+      added: tick,
+      changed: tick
+    }
+  }
+}
+
+pub struct RemovedIter<'a> {
+  removed: slice::Iter<'a, (Entity, usize)>,
+  since: usize
+}
+
+impl<'a> RemovedIter<'a> {
+  pub fn new(removed: &'a [(Entity, usize)], since: usize) -> RemovedIter<'a> {
+    RemovedIter {
+      removed: removed.iter(),
+      since: since
+    }
+  }
+}
+
+impl<'a> Iterator for RemovedIter<'a> {
+  type Item = Entity;
+
+  fn next(&mut self) -> Option<Entity> {
+    while let Some(&(e, tick)) = self.removed.next() {
+      if tick > self.since {
+        return Some(e);
+      }
+    }
+    None
+  }
+}
@@ -0,0 +1,198 @@
+use piston_window::math::{self, Matrix2d, Vec2d};
+use yaml_rust::Yaml;
+use yaml_rust::yaml::Hash;
+
+use super::{Entity, Component, World, VecStorage, Parent, Serializable, EntityMap, float_to_yaml, yaml_to_float};
+
+// Position, rotation (in radians) and scale relative to the parent entity, or to the world for roots
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Transform {
+  pub translation: Vec2d,
+  pub rotation: f64,
+  pub scale: Vec2d
+}
+
+impl Component for Transform {
+  type Storage = VecStorage<Self>;
+}
+
+impl Transform {
+  pub fn new() -> Transform {
+    Transform {
+      translation: [0.0, 0.0],
+      rotation: 0.0,
+      scale: [1.0, 1.0]
+    }
+  }
+
+  pub fn at(x: f64, y: f64) -> Transform {
+    Transform { translation: [x, y], .. Transform::new() }
+  }
+
+  // scales first, then rotates, then translates
+  pub fn matrix(&self) -> Matrix2d {
+    let rotated = math::multiply(math::rotate_radians(self.rotation), math::scale(self.scale[0], self.scale[1]));
+    math::multiply(math::translate(self.translation), rotated)
+  }
+}
+
+fn vec2d_to_yaml(v: Vec2d) -> Yaml {
+  Yaml::Array(vec![float_to_yaml(v[0]), float_to_yaml(v[1])])
+}
+
+fn yaml_to_vec2d(data: &Yaml) -> Option<Vec2d> {
+  match (yaml_to_float(&data[0]), yaml_to_float(&data[1])) {
+    (Some(x), Some(y)) => Some([x, y]),
+    _ => None
+  }
+}
+
+impl Serializable for Transform {
+  fn serialize(&self) -> Yaml {
+    let mut hash = Hash::new();
+    hash.insert(Yaml::String("translation".into()), vec2d_to_yaml(self.translation));
+    hash.insert(Yaml::String("rotation".into()), float_to_yaml(self.rotation));
+    hash.insert(Yaml::String("scale".into()), vec2d_to_yaml(self.scale));
+    Yaml::Hash(hash)
+  }
+
+  // missing fields fall back to the identity transform
+  fn deserialize(data: &Yaml, _entities: &EntityMap) -> Option<Transform> {
+    let identity = Transform::new();
+    let field = |name: &str| if data[name].is_badvalue() { None } else { Some(&data[name]) };
+    Some(Transform {
+      translation: match field("translation") {
+        Some(translation) => yaml_to_vec2d(translation)?,
+        None => identity.translation
+      },
+      rotation: match field("rotation") {
+        Some(rotation) => yaml_to_float(rotation)?,
+        None => identity.rotation
+      },
+      scale: match field("scale") {
+        Some(scale) => yaml_to_vec2d(scale)?,
+        None => identity.scale
+      }
+    })
+  }
+}
+
+// World-space matrix written by World::propagate_transforms; can be passed straight to
+// piston_window's drawing functions, e.g. rectangle.draw(rect, &c.draw_state, c.transform.append_transform(global.0), g)
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct GlobalTransform(pub Matrix2d);
+
+impl Component for GlobalTransform {
+  type Storage = VecStorage<Self>;
+}
+
+impl GlobalTransform {
+  pub fn translation(&self) -> Vec2d {
+    math::transform_pos(self.0, [0.0, 0.0])
+  }
+}
+
+impl World {
+  // Composes every Transform with those of its ancestors and stores the result as the entity's
+  // GlobalTransform. Entities in the hierarchy without a Transform pass their parent's matrix through
+  // and lose any GlobalTransform left over from before.
+  pub fn propagate_transforms(&mut self) {
+    let roots: Vec<Entity> = self.iter()
+      .filter(|&&e| !self.has::<Parent>(e))
+      .filter(|&&e| self.has::<Transform>(e) || !self.children(e).is_empty())
+      .cloned()
+      .collect();
+    let mut globals = Vec::new();
+    let mut pending: Vec<(Entity, Matrix2d)> = roots.into_iter().map(|e| (e, math::identity())).collect();
+    while let Some((e, parent_matrix)) = pending.pop() {
+      let matrix = match self.get::<Transform>(e) {
+        Some(transform) => {
+          let matrix = math::multiply(parent_matrix, transform.matrix());
+          globals.push((e, matrix));
+          matrix
+        },
+        None => parent_matrix
+      };
+      pending.extend(self.children(e).iter().map(|&child| (child, matrix)));
+    }
+    let stale: Vec<Entity> = self.get_comp::<GlobalTransform>()
+      .map(|(&e, _)| e)
+      .filter(|&e| !self.has::<Transform>(e))
+      .collect();
+    for e in stale {
+      self.remove_comp::<GlobalTransform>(e);
+    }
+    for (e, matrix) in globals {
+      // only write matrices that moved so Changed<GlobalTransform> stays meaningful
+      let current = self.get::<GlobalTransform>(e).map(|global| global.0);
+      if current != Some(matrix) {
+        self.add_comp(e, GlobalTransform(matrix));
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod transform_tests {
+  use std::f64::consts::PI;
+  use super::super::*;
+
+  // describe: transform propagation
+
+  fn assert_near(actual: [f64; 2], expected: [f64; 2]) {
+    assert!((actual[0] - expected[0]).abs() < 1e-9 && (actual[1] - expected[1]).abs() < 1e-9,
+      "expected {:?}, got {:?}", expected, actual);
+  }
+
+  // it should give roots a global transform equal to their local one
+  #[test]
+  fn test_root() {
+    let mut test_world = World::new();
+    let entity = test_world.spawn_bundle((Transform::at(3.0, 4.0),));
+    test_world.propagate_transforms();
+    let global = *test_world.get::<GlobalTransform>(entity).unwrap();
+    assert_eq!(global.0, Transform::at(3.0, 4.0).matrix());
+    assert_near(global.translation(), [3.0, 4.0]);
+  }
+
+  // it should compose parent transforms into their children
+  #[test]
+  fn test_hierarchy() {
+    let mut test_world = World::new();
+    let body = test_world.spawn_bundle((Transform { rotation: PI / 2.0, scale: [2.0, 2.0], .. Transform::at(10.0, 0.0) },));
+    let pivot = test_world.create();
+    let hat = test_world.spawn_bundle((Transform::at(1.0, 0.0),));
+    test_world.set_parent(pivot, body);
+    test_world.set_parent(hat, pivot);
+    test_world.propagate_transforms();
+    assert_eq!(test_world.has::<GlobalTransform>(pivot), false);
+    assert_near(test_world.get::<GlobalTransform>(hat).unwrap().translation(), [10.0, 2.0]);
+    test_world.get_mut::<Transform>(body).unwrap().translation = [0.0, 5.0];
+    test_world.propagate_transforms();
+    assert_near(test_world.get::<GlobalTransform>(hat).unwrap().translation(), [0.0, 7.0]);
+    test_world.remove_comp::<Transform>(hat);
+    test_world.propagate_transforms();
+    assert_eq!(test_world.has::<GlobalTransform>(hat), false);
+  }
+
+  // it should only mark global transforms as changed when they actually move
+  #[test]
+  fn test_unchanged() {
+    let mut test_world = World::new();
+    let entity = test_world.spawn_bundle((Transform::at(1.0, 1.0),));
+    test_world.propagate_transforms();
+    test_world.clear_trackers();
+    test_world.propagate_transforms();
+    assert_eq!(test_world.is_changed::<GlobalTransform>(entity), false);
+  }
+
+  // it should have the transform components registered in every new state
+  #[test]
+  fn test_registered() {
+    let mut test_world = World::new();
+    push_state(&mut test_world);
+    let entity = test_world.create();
+    test_world.add_comp(entity, Transform::new());
+    assert_eq!(test_world.get_comp::<GlobalTransform>().len(), 0);
+  }
+}
@@ -1,48 +1,525 @@
-use piston_window::PistonWindow;
+use std::time::{Duration, Instant};
+use piston_window::{PistonWindow, Event, RenderArgs, Transformed};
 use super::ecs;
-use super::state::{State, StateTrans};
+use super::state::{State, StateTrans, Transitions};
 use super::asset_manager::AssetManager;
 use super::config_loader;
+use super::transition::{Transition, Effect, Opacity};
+
+// Seconds since the last update, kept as a global resource so systems and every state can read it
+pub struct DeltaTime(pub f64);
+
+// How often World::update_events runs; events are dropped on the second run after they were sent.
+// Under a fixed timestep an update is a frame that ran at least one fixed step, so readers in
+// update and in fixed_update both get a look at every event before it is dropped.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum EventClearing {
+  EveryUpdates(u32),
+  // left to the game, which calls World::update_events itself
+  Manual
+}
+
+// Runs fixed_update at a steady rate of real time instead of on piston's update events.
+// Set from the fixed_update_rate and max_fixed_updates config keys.
+pub struct FixedTimestep {
+  step: f64,
+  max_steps: u32,
+  accumulated: f64
+}
+
+impl FixedTimestep {
+  pub fn new(hz: f64, max_steps: u32) -> FixedTimestep {
+    if hz <= 0.0 {
+      panic!("Error: Fixed update rate must be positive")
+    }
+    FixedTimestep {
+      step: 1.0 / hz,
+      max_steps: max_steps.max(1),
+      accumulated: 0.0
+    }
+  }
+
+  pub fn step(&self) -> f64 {
+    self.step
+  }
+
+  // how many steps the frame owes; after a frame too slow to catch up with in max_steps,
+  // the backlog is dropped so one slow frame can't make every later frame slower
+  fn advance(&mut self, dt: f64) -> u32 {
+    self.accumulated += dt;
+    let mut steps = 0;
+    while self.accumulated >= self.step && steps < self.max_steps {
+      self.accumulated -= self.step;
+      steps += 1;
+    }
+    if self.accumulated >= self.step {
+      self.accumulated %= self.step;
+    }
+    steps
+  }
+
+  // how far the clock is between the last step and the next, from 0 to 1
+  fn alpha(&self) -> f64 {
+    self.accumulated / self.step
+  }
+}
+
+fn as_secs(duration: Duration) -> f64 {
+  duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+}
+
+// seconds since the previous frame
+fn tick(last_frame: &mut Instant) -> f64 {
+  let now = Instant::now();
+  let dt = as_secs(now - *last_frame);
+  *last_frame = now;
+  dt
+}
+
+// a transition nested in an animated one just plays along with it
+fn unanimated(state_trans: StateTrans) -> StateTrans {
+  match state_trans {
+    StateTrans::Animate(state_trans, _) => unanimated(*state_trans),
+    state_trans => state_trans
+  }
+}
+
+// how many states PopTo has to pop to uncover the topmost state with the id, if there is one
+fn pops_to(state_stack: &[Box<State>], id: &str) -> Option<usize> {
+  state_stack.iter().rposition(|state| state.id() == Some(id))
+    .map(|index| state_stack.len() - 1 - index)
+}
+
+// What a playing transition still has to do to the state stack
+enum Stage {
+  // a fade's transition, applied once the screen is covered
+  Fade(Option<StateTrans>),
+  // slides and crossfades run between the top state and the one below it, which are already
+  // both on the stack: pushing has happened, popping happens at the end, and a swap exits
+  // and drops the state below at the end
+  Push,
+  Pop,
+  Swap
+}
+
+struct Animation {
+  transition: Transition,
+  elapsed: f64,
+  stage: Stage
+}
 
 pub struct Game {
-  state_stack: Vec<Box<State>>
+  state_stack: Vec<Box<State>>,
+  dispatcher: ecs::Dispatcher,
+  event_clearing: EventClearing,
+  updates_since_clear: u32,
+  animation: Option<Animation>,
+  // transitions waiting for the playing animation to finish, in the order they were asked for
+  held: Vec<StateTrans>
 }
 
 impl Game {
   pub fn new<T>(init_state: T) -> Game where T: State + 'static {
     Game {
-      state_stack: vec![Box::new(init_state)]
+      state_stack: vec![Box::new(init_state)],
+      dispatcher: ecs::Dispatcher::new(),
+      event_clearing: EventClearing::EveryUpdates(1),
+      updates_since_clear: 0,
+      animation: None,
+      held: Vec::new()
     }
   }
 
+  // systems added here run against the current state's world before each fixed_update
+  pub fn dispatcher(&mut self) -> &mut ecs::Dispatcher {
+    &mut self.dispatcher
+  }
+
+  pub fn set_event_clearing(&mut self, event_clearing: EventClearing) {
+    if event_clearing == EventClearing::EveryUpdates(0) {
+      panic!("Error: Event clearing interval must be at least one update")
+    }
+    self.event_clearing = event_clearing;
+  }
+
   pub fn start_game(&mut self, config_path: &str) {
-    let mut window: PistonWindow = config_loader::ConfigLoader.load_config(config_path);
+    let (mut window, mut fixed_timestep) = config_loader::ConfigLoader.load_config(config_path);
     let mut world = ecs::World::new();
     let mut asset_manager = AssetManager::new();
+    let mut last_frame = Instant::now();
+    let mut last_update_dt = 0.0;
+    let transitions = Transitions::new();
+    world.insert_global_resource(transitions.clone());
 
     self.current_state().init(&mut window, &mut world, &mut asset_manager);
 
     while let Some(event) = window.next() {
-      let state_trans = self.current_state().update(&mut window, event, &mut world, &mut asset_manager);
-      match state_trans {
-        StateTrans::None => (),
-        StateTrans::Pop => {
-          self.current_state().exit(&mut window, &mut world, &mut asset_manager);
-          self.pop(&mut world)
+      // input is dropped and no updates run while a transition plays, but commands still land
+      // and transitions asked for meanwhile are held until it finishes
+      if self.animation.is_some() {
+        if let Event::Render(args) = event {
+          let dt = tick(&mut last_frame);
+          self.animate(&mut window, &event, args, dt, &mut world, &mut asset_manager);
+          world.maintain();
+          if !self.state_stack.is_empty() {
+            self.held.extend(transitions.take());
+            self.apply_held(&mut window, &mut world, &mut asset_manager);
+          }
+          if self.state_stack.is_empty() {
+            break;
+          }
+        }
+        continue;
+      }
+      let state_trans = match event {
+        Event::Input(ref input) => self.current_state().handle_input(&mut window, input, &mut world, &mut asset_manager),
+        // with a fixed timestep the steps come from the frame clock instead
+        Event::Update(_) if fixed_timestep.is_some() => StateTrans::None,
+        Event::Update(args) => {
+          last_update_dt = args.dt;
+          let state_trans = self.fixed_step(&mut window, args.dt, &mut world, &mut asset_manager);
+          self.count_update(&mut world);
+          state_trans
         },
-        StateTrans::Push(state) => {
-          self.push(&mut world, state);
-          self.current_state().init(&mut window, &mut world, &mut asset_manager);
+        Event::Render(args) => {
+          let dt = tick(&mut last_frame);
+          let (state_trans, stepped, alpha) = match fixed_timestep {
+            Some(ref mut timestep) => {
+              let (state_trans, stepped) = self.fixed_steps(timestep, dt, &mut window, &mut world, &mut asset_manager);
+              (state_trans, stepped, timestep.alpha())
+            },
+            None if last_update_dt > 0.0 => (StateTrans::None, false, (args.ext_dt / last_update_dt).min(1.0)),
+            None => (StateTrans::None, false, 0.0)
+          };
+          // a transition from the fixed steps is applied right away, and the frame is still drawn
+          // by whatever ends up on top, or by the animation it started
+          match state_trans {
+            StateTrans::None => (),
+            state_trans => {
+              self.settle(state_trans, &transitions, &mut window, &mut world, &mut asset_manager);
+              if self.state_stack.is_empty() {
+                break;
+              }
+            }
+          }
+          let state_trans = if self.animation.is_some() {
+            self.animate(&mut window, &event, args, 0.0, &mut world, &mut asset_manager);
+            StateTrans::None
+          } else {
+            self.frame(&mut window, &event, dt, alpha, &mut world, &mut asset_manager)
+          };
+          // once per frame, after update has read what the fixed steps sent
+          if stepped {
+            self.count_update(&mut world);
+          }
+          state_trans
         },
-        StateTrans::Swap(state) => {
-          self.current_state().exit(&mut window, &mut world, &mut asset_manager);
-          self.switch(&mut world, state);
-          self.current_state().init(&mut window, &mut world, &mut asset_manager);
+        _ => StateTrans::None
+      };
+      self.settle(state_trans, &transitions, &mut window, &mut world, &mut asset_manager);
+      if self.state_stack.is_empty() {
+        break;
+      }
+    }
+  }
+
+  // deferred structural changes land before the state stack can change underneath them, then
+  // state_trans goes after the transitions already held
+  fn settle(&mut self, state_trans: StateTrans, transitions: &Transitions, window: &mut PistonWindow, world: &mut ecs::World, asset_manager: &mut AssetManager) {
+    world.maintain();
+    self.held.push(state_trans);
+    self.held.extend(transitions.take());
+    self.apply_held(window, world, asset_manager);
+  }
+
+  // applies the held transitions in order, stopping early if one starts an animation
+  fn apply_held(&mut self, window: &mut PistonWindow, world: &mut ecs::World, asset_manager: &mut AssetManager) {
+    while self.animation.is_none() && !self.held.is_empty() {
+      let state_trans = self.held.remove(0);
+      self.transition(state_trans, window, world, asset_manager);
+      if self.state_stack.is_empty() {
+        self.held.clear();
+        return;
+      }
+    }
+  }
+
+  fn transition(&mut self, state_trans: StateTrans, window: &mut PistonWindow, world: &mut ecs::World, asset_manager: &mut AssetManager) {
+    match state_trans {
+      StateTrans::None => (),
+      StateTrans::Pop => self.pop_n(1, window, world, asset_manager),
+      StateTrans::PopN(n) => self.pop_n(n, window, world, asset_manager),
+      StateTrans::PopTo(id) => {
+        if let Some(n) = pops_to(&self.state_stack, id) {
+          self.pop_n(n, window, world, asset_manager);
         }
+      },
+      StateTrans::Push(state) => {
+        self.current_state().on_pause(window, world, asset_manager);
+        self.push(world, state);
+        self.current_state().init(window, world, asset_manager);
+      },
+      StateTrans::Swap(state) => {
+        self.current_state().exit(window, world, asset_manager);
+        self.switch(world, state);
+        self.current_state().init(window, world, asset_manager);
+      },
+      StateTrans::Replace(states) => {
+        let n = self.state_stack.len();
+        self.pop_n(n, window, world, asset_manager);
+        for state in states {
+          if let Some(below) = self.state_stack.last_mut() {
+            below.on_pause(window, world, asset_manager);
+          }
+          self.push(world, state);
+          self.current_state().init(window, world, asset_manager);
+        }
+      },
+      StateTrans::Animate(state_trans, transition) =>
+        self.start_animation(*state_trans, transition, window, world, asset_manager),
+      StateTrans::Quit => {
+        let n = self.state_stack.len();
+        self.pop_n(n, window, world, asset_manager);
       }
     }
   }
 
+  // exits and pops states from the top, then resumes the one left on top, if any
+  fn pop_n(&mut self, n: usize, window: &mut PistonWindow, world: &mut ecs::World, asset_manager: &mut AssetManager) {
+    let n = n.min(self.state_stack.len());
+    if n == 0 {
+      return;
+    }
+    for _ in 0..n {
+      self.current_state().exit(window, world, asset_manager);
+      self.pop(world);
+    }
+    if let Some(state) = self.state_stack.last_mut() {
+      state.on_resume(window, world, asset_manager);
+    }
+  }
+
+  // systems only run against the top state's world
+  fn fixed_step(&mut self, window: &mut PistonWindow, dt: f64, world: &mut ecs::World, asset_manager: &mut AssetManager) -> StateTrans {
+    world.insert_global_resource(DeltaTime(dt));
+    run_systems(&mut self.dispatcher, world);
+    let lowest = self.lowest_updating();
+    self.each_covered(lowest, world, |state, world| {
+      state.fixed_update(window, dt, world, asset_manager);
+      world.maintain();
+      world.propagate_transforms();
+    });
+    let state_trans = self.current_state().fixed_update(window, dt, world, asset_manager);
+    world.maintain();
+    world.propagate_transforms();
+    world.shared(|shared| shared.propagate_transforms());
+    // change and removal events live for one step
+    world.clear_trackers();
+    state_trans
+  }
+
+  // counts an update towards EventClearing
+  fn count_update(&mut self, world: &mut ecs::World) {
+    if let EventClearing::EveryUpdates(interval) = self.event_clearing {
+      self.updates_since_clear += 1;
+      if self.updates_since_clear >= interval {
+        world.update_events();
+        self.updates_since_clear = 0;
+      }
+    }
+  }
+
+  // stops at the first step that asks for a transition; the time owed for the rest is dropped.
+  // Also returns whether any step ran.
+  fn fixed_steps(&mut self, timestep: &mut FixedTimestep, dt: f64, window: &mut PistonWindow, world: &mut ecs::World, asset_manager: &mut AssetManager) -> (StateTrans, bool) {
+    let steps = timestep.advance(dt);
+    for _ in 0..steps {
+      match self.fixed_step(window, timestep.step(), world, asset_manager) {
+        StateTrans::None => (),
+        state_trans => return (state_trans, true)
+      }
+    }
+    (StateTrans::None, steps > 0)
+  }
+
+  // a transition returned by update still lets the current state finish the frame
+  fn frame(&mut self, window: &mut PistonWindow, event: &Event, dt: f64, alpha: f64, world: &mut ecs::World, asset_manager: &mut AssetManager) -> StateTrans {
+    let lowest = self.lowest_updating();
+    self.each_covered(lowest, world, |state, world| {
+      state.update(window, dt, world, asset_manager);
+      world.maintain();
+      world.propagate_transforms();
+      state.late_update(window, world, asset_manager);
+      world.maintain();
+    });
+    let state_trans = self.current_state().update(window, dt, world, asset_manager);
+    world.maintain();
+    world.propagate_transforms();
+    world.shared(|shared| shared.propagate_transforms());
+    self.current_state().late_update(window, world, asset_manager);
+    world.maintain();
+    let lowest = self.lowest_visible();
+    self.render(window, event, lowest, alpha, world, asset_manager, |_| [0.0, 0.0], None);
+    state_trans
+  }
+
+  // draws the states from lowest up, each against its own world state and moved by shift,
+  // then fills the rectangle of cover on top
+  fn render<F: Fn(usize) -> [f64; 2]>(&mut self, window: &mut PistonWindow, event: &Event, lowest: usize, alpha: f64,
+    world: &mut ecs::World, asset_manager: &mut AssetManager, shift: F, cover: Option<([f32; 4], [f64; 4])>) {
+    let top = self.state_stack.len() - 1;
+    let state_stack = &mut self.state_stack;
+    window.draw_2d(event, |context, graphics| {
+      for index in lowest..top + 1 {
+        let offset = shift(index);
+        let context = context.trans(offset[0], offset[1]);
+        let state = &mut state_stack[index];
+        ecs::with_covered_state(world, top - index, |world| state.render(context, graphics, alpha, world, asset_manager));
+      }
+      if let Some((color, rect)) = cover {
+        piston_window::rectangle(color, rect, context.transform, graphics);
+      }
+    });
+  }
+
+  fn start_animation(&mut self, state_trans: StateTrans, transition: Transition, window: &mut PistonWindow, world: &mut ecs::World, asset_manager: &mut AssetManager) {
+    let poppable = self.state_stack.len() > 1;
+    let stage = match (transition.effect(), unanimated(state_trans)) {
+      (Effect::Fade(_), state_trans) => Stage::Fade(Some(state_trans)),
+      (_, StateTrans::None) => return,
+      (_, StateTrans::Push(state)) => {
+        self.transition(StateTrans::Push(state), window, world, asset_manager);
+        Stage::Push
+      },
+      // the last state has nothing to reveal, so it goes right away
+      (_, StateTrans::Pop) if !poppable => return self.transition(StateTrans::Pop, window, world, asset_manager),
+      (_, StateTrans::Pop) => Stage::Pop,
+      // the leaving state exits before the coming one inits, as with the plain Swap, but its world
+      // stays to be drawn until the effect ends. The coming state starts out as the plain Swap
+      // would leave it, with the leaving one set aside.
+      (_, StateTrans::Swap(state)) => {
+        self.current_state().exit(window, world, asset_manager);
+        ecs::with_top_state_aside(world, |world| {
+          self.push(world, state);
+          self.current_state().init(window, world, asset_manager);
+        });
+        Stage::Swap
+      },
+      _ => panic!("Error: Crossfades and slides only work with Push, Pop and Swap transitions")
+    };
+    self.animation = Some(Animation {
+      transition: transition,
+      elapsed: 0.0,
+      stage: stage
+    });
+  }
+
+  // states are drawn as of the last fixed update while a transition plays
+  fn animate(&mut self, window: &mut PistonWindow, event: &Event, args: RenderArgs, dt: f64, world: &mut ecs::World, asset_manager: &mut AssetManager) {
+    let mut animation = self.animation.take().expect("Error: No transition is playing");
+    animation.elapsed += dt;
+    let progress = (animation.elapsed / animation.transition.duration()).min(1.0);
+    let size = [args.width as f64, args.height as f64];
+    match animation.stage {
+      Stage::Fade(ref mut pending) => {
+        if progress >= 0.5 {
+          if let Some(state_trans) = pending.take() {
+            self.transition(state_trans, window, world, asset_manager);
+            if self.state_stack.is_empty() {
+              return;
+            }
+          }
+        }
+        let mut color = match animation.transition.effect() {
+          Effect::Fade(color) => color,
+          _ => unreachable!()
+        };
+        color[3] *= animation.transition.cover(progress) as f32;
+        let lowest = self.lowest_visible();
+        self.render(window, event, lowest, 0.0, world, asset_manager, |_| [0.0, 0.0], Some((color, [0.0, 0.0, size[0], size[1]])));
+      },
+      _ => {
+        let top = self.state_stack.len() - 1;
+        let (leaving, coming) = match animation.stage {
+          Stage::Pop => (top, top - 1),
+          _ => (top - 1, top)
+        };
+        if animation.transition.effect() == Effect::Crossfade {
+          let (leaving_opacity, coming_opacity) = animation.transition.opacities(progress);
+          ecs::with_covered_state(world, top - leaving, |world| world.insert_resource(Opacity(leaving_opacity)));
+          ecs::with_covered_state(world, top - coming, |world| world.insert_resource(Opacity(coming_opacity)));
+        }
+        let (leaving_offset, coming_offset) = animation.transition.offsets(progress, size);
+        let lowest = self.lowest_reached_from(top - 1, |state| state.is_transparent());
+        self.render(window, event, lowest, 0.0, world, asset_manager, |index| {
+          if index == leaving {
+            leaving_offset
+          } else if index == coming {
+            coming_offset
+          } else {
+            [0.0, 0.0]
+          }
+        }, None);
+      }
+    }
+    if progress < 1.0 {
+      self.animation = Some(animation);
+    } else {
+      self.finish_animation(animation.stage, window, world, asset_manager);
+    }
+  }
+
+  fn finish_animation(&mut self, stage: Stage, window: &mut PistonWindow, world: &mut ecs::World, asset_manager: &mut AssetManager) {
+    if let Stage::Fade(_) = stage {
+      return;
+    }
+    for depth in 0..2 {
+      ecs::with_covered_state(world, depth, |world| { world.remove_resource::<Opacity>(); });
+    }
+    match stage {
+      Stage::Pop => self.transition(StateTrans::Pop, window, world, asset_manager),
+      // the leaving state already exited when the effect started
+      Stage::Swap => {
+        let below = self.state_stack.len() - 2;
+        ecs::remove_covered_state(world, 1);
+        self.state_stack.remove(below);
+      },
+      _ => ()
+    }
+  }
+
+  fn lowest_updating(&self) -> usize {
+    self.lowest_reached(|state| !state.blocks_update())
+  }
+
+  fn lowest_visible(&self) -> usize {
+    self.lowest_reached(|state| state.is_transparent())
+  }
+
+  fn lowest_reached<F: Fn(&State) -> bool>(&self, see_through: F) -> usize {
+    let top = self.state_stack.len() - 1;
+    self.lowest_reached_from(top, see_through)
+  }
+
+  // walks down from start for as long as see_through lets callbacks past each state
+  fn lowest_reached_from<F: Fn(&State) -> bool>(&self, start: usize, see_through: F) -> usize {
+    let mut lowest = start;
+    while lowest > 0 && see_through(&*self.state_stack[lowest]) {
+      lowest -= 1;
+    }
+    lowest
+  }
+
+  // runs f bottom-up on the states from lowest up to but not including the top one,
+  // each against its own world state
+  fn each_covered<F: FnMut(&mut Box<State>, &mut ecs::World)>(&mut self, lowest: usize, world: &mut ecs::World, mut f: F) {
+    let top = self.state_stack.len() - 1;
+    for index in lowest..top {
+      let state = &mut self.state_stack[index];
+      ecs::with_covered_state(world, top - index, |world| f(state, world));
+    }
+  }
+
   fn current_state(&mut self) -> &mut Box<State> {
     self.state_stack.last_mut().expect("Error: Could not find current state; empty state stack")
   }
@@ -53,14 +530,96 @@ impl Game {
   }
 
   fn push(&mut self, world: &mut ecs::World, state: Box<State>) {
-    ecs::push_state(world);
+    ecs::push_state_with(world, state.world_inheritance());
     self.state_stack.push(state);
   }
 
   fn switch(&mut self, world: &mut ecs::World, state: Box<State>) {
-    ecs::switch_state(world);
+    ecs::pop_state(world);
+    ecs::push_state_with(world, state.world_inheritance());
     self.state_stack.pop().expect("Error: Attempted to switch states with empty state stack");
     self.state_stack.push(state);
   }
 
-}
\ No newline at end of file
+}
+
+// the systems run against the top state's world, so the commands they queue are applied there
+// before the covered states get their turn
+fn run_systems(dispatcher: &mut ecs::Dispatcher, world: &mut ecs::World) {
+  dispatcher.run(world);
+  world.maintain();
+}
+
+#[cfg(test)]
+mod game_tests {
+  use super::{FixedTimestep, pops_to, run_systems};
+  use ecs;
+  use state::State;
+
+  // describe: the fixed timestep
+
+  // it should run one step per elapsed step length and keep the remainder as alpha
+  #[test]
+  fn test_advance() {
+    let mut timestep = FixedTimestep::new(10.0, 5);
+    assert_eq!(timestep.advance(0.05), 0);
+    assert_eq!(timestep.advance(0.1), 1);
+    assert!((timestep.alpha() - 0.5).abs() < 1e-9);
+    assert_eq!(timestep.advance(0.25), 3);
+  }
+
+  // it should drop the backlog a slow frame leaves beyond the maximum number of steps
+  #[test]
+  fn test_spiral_of_death() {
+    let mut timestep = FixedTimestep::new(10.0, 2);
+    assert_eq!(timestep.advance(1.05), 2);
+    assert!(timestep.alpha() < 1.0);
+    assert_eq!(timestep.advance(0.0), 0);
+  }
+
+  // describe: systems under covered states
+
+  struct Cull(ecs::Entity);
+
+  impl ecs::System for Cull {
+    fn run(&mut self, world: &mut ecs::World) {
+      world.commands().remove(self.0);
+    }
+  }
+
+  // it should apply the systems' commands to the top state, not the one underneath
+  #[test]
+  fn test_commands_stay_on_top() {
+    let mut world = ecs::World::new();
+    let below = world.create();
+    ecs::push_state(&mut world);
+    let top = world.create();
+    let mut dispatcher = ecs::Dispatcher::new();
+    dispatcher.add_system("cull", Cull(top));
+    run_systems(&mut dispatcher, &mut world);
+    ecs::with_covered_state(&mut world, 1, |world| {
+      world.maintain();
+      assert_eq!(world.contains(below), true);
+    });
+    assert_eq!(world.contains(top), false);
+  }
+
+  // describe: popping back to a state
+
+  struct Named(&'static str);
+
+  impl State for Named {
+    fn id(&self) -> Option<&str> { Some(self.0) }
+  }
+
+  // it should pop down to the topmost state with the id, and not at all when none has it
+  #[test]
+  fn test_pops_to() {
+    let state_stack: Vec<Box<State>> = vec![Box::new(Named("menu")), Box::new(Named("level")), Box::new(Named("level")),
+      Box::new(Named("pause"))];
+    assert_eq!(pops_to(&state_stack, "menu"), Some(3));
+    assert_eq!(pops_to(&state_stack, "level"), Some(1));
+    assert_eq!(pops_to(&state_stack, "pause"), Some(0));
+    assert_eq!(pops_to(&state_stack, "credits"), None);
+  }
+}
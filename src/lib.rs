@@ -1,12 +1,20 @@
 extern crate piston_window;
 extern crate yaml_rust;
+extern crate rayon;
 
 #[macro_use]
 extern crate mopa;
 
 mod ecs;
-pub use ecs::{Entity, Component, World};
+pub use ecs::{EcsError, Entity, Component, Resource, World, EntityBuilder, Bundle, Commands, Query, QueryIter, With, Without, Added, Changed};
+pub use ecs::{ChangeTicks, RemovedIter, Parent, Children, Transform, GlobalTransform};
+pub use ecs::{Serializable, EntityMap, WorldSerializer, SaveFormat, SerializeError, Prefabs, WorldSnapshot};
+pub use ecs::{Name, NameStorage, TagIter, Event, EventReader, EventIter, StateInheritance};
+pub use ecs::{entity_to_yaml, float_to_yaml, yaml_to_float};
+pub use ecs::{Storage, StorageIter, StorageIterMut, HashMapStorage, VecStorage, VecIter, VecIterMut, SparseSetStorage, NullStorage};
+pub use ecs::{System, Dispatcher, Stage, ParSystem, ParDispatcher, SystemData};
 mod config_loader;
 pub mod state;
 pub mod asset_manager;
-pub mod game;
\ No newline at end of file
+pub mod game;
+pub mod transition;
\ No newline at end of file
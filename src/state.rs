@@ -1,16 +1,87 @@
+use std::mem;
+use std::sync::{Arc, Mutex};
 use piston_window;
 use super::ecs;
 use super::asset_manager;
+use super::transition::Transition;
 
+// The game loop ends once the stack is empty, e.g. after Quit or popping the last state
 pub enum StateTrans {
   Pop,
+  // pops this many states, or all of them if there are fewer
+  PopN(usize),
+  // pops the states above the topmost one whose id matches; does nothing if none does
+  PopTo(&'static str),
   Push(Box<State>),
   Swap(Box<State>),
+  // empties the stack and pushes these in order, the last one ending up on top
+  Replace(Vec<Box<State>>),
+  // plays the transition's effect around another transition
+  Animate(Box<StateTrans>, Transition),
+  Quit,
   None
 }
 
+trait Request: Send {
+  fn make(self: Box<Self>) -> StateTrans;
+}
+
+impl<F: FnOnce() -> StateTrans + Send> Request for F {
+  fn make(self: Box<Self>) -> StateTrans {
+    (*self)()
+  }
+}
+
+// Lets code without a State callback to return from, such as systems, ask for transitions.
+// Game::start_game inserts a handle as a global resource and applies the requests in order after
+// each event, following the transition the event's callback returned. States aren't Send, so
+// requests are closures that build the transition once it is applied.
+#[derive(Clone)]
+pub struct Transitions {
+  queue: Arc<Mutex<Vec<Box<Request>>>>
+}
+
+impl Transitions {
+  pub fn new() -> Transitions {
+    Transitions { queue: Arc::new(Mutex::new(Vec::new())) }
+  }
+
+  pub fn request<F: FnOnce() -> StateTrans + Send + 'static>(&self, request: F) {
+    self.queue.lock().expect("Error: Transition queue lock was poisoned").push(Box::new(request));
+  }
+
+  pub fn take(&self) -> Vec<StateTrans> {
+    let requests = mem::replace(&mut *self.queue.lock().expect("Error: Transition queue lock was poisoned"), Vec::new());
+    requests.into_iter().map(|request| request.make()).collect()
+  }
+}
+
+// Game::start_game sorts piston's events into these callbacks; override the ones the state needs
 pub trait State {
   fn init(&mut self, window: &mut piston_window::PistonWindow, world: &mut ecs::World, asset_manager: &mut asset_manager::AssetManager) {}
-  fn update(&mut self, window: &mut piston_window::PistonWindow, event: piston_window::Event, world: &mut ecs::World, asset_manager: &mut asset_manager::AssetManager) -> StateTrans;
+  // keyboard, mouse, controller and window events, as they arrive
+  fn handle_input(&mut self, window: &mut piston_window::PistonWindow, input: &piston_window::Input, world: &mut ecs::World, asset_manager: &mut asset_manager::AssetManager) -> StateTrans { StateTrans::None }
+  // runs at piston's update rate, or the config's fixed_update_rate, right after the dispatcher's
+  // systems; dt is the same every step
+  fn fixed_update(&mut self, window: &mut piston_window::PistonWindow, dt: f64, world: &mut ecs::World, asset_manager: &mut asset_manager::AssetManager) -> StateTrans { StateTrans::None }
+  // runs once per frame before rendering; dt is the time since the previous frame
+  fn update(&mut self, window: &mut piston_window::PistonWindow, dt: f64, world: &mut ecs::World, asset_manager: &mut asset_manager::AssetManager) -> StateTrans { StateTrans::None }
+  // runs after update once its commands are applied and transforms propagated, e.g. to move a camera
+  fn late_update(&mut self, window: &mut piston_window::PistonWindow, world: &mut ecs::World, asset_manager: &mut asset_manager::AssetManager) {}
+  // alpha runs from 0 to 1 between one fixed update and the next, for interpolating positions
+  fn render(&mut self, context: piston_window::Context, graphics: &mut piston_window::G2d, alpha: f64, world: &mut ecs::World, asset_manager: &mut asset_manager::AssetManager) {}
   fn exit(&mut self, window: &mut piston_window::PistonWindow, world: &mut ecs::World, asset_manager: &mut asset_manager::AssetManager) {}
+  // called when another state is pushed on top of this one, before the new state's init
+  fn on_pause(&mut self, window: &mut piston_window::PistonWindow, world: &mut ecs::World, asset_manager: &mut asset_manager::AssetManager) {}
+  // called when the state on top of this one is popped, after its exit
+  fn on_resume(&mut self, window: &mut piston_window::PistonWindow, world: &mut ecs::World, asset_manager: &mut asset_manager::AssetManager) {}
+  // whether the states below this one are still drawn, underneath it
+  fn is_transparent(&self) -> bool { false }
+  // whether the states below this one stop getting fixed_update, update and late_update.
+  // Covered states never get input, and the transitions they return are ignored.
+  fn blocks_update(&self) -> bool { true }
+  // used by StateTrans::PopTo to find the state to return to
+  fn id(&self) -> Option<&str> { None }
+  // how much of the state below this one's world starts out with or can see
+  fn world_inheritance(&self) -> ecs::StateInheritance { ecs::StateInheritance::Empty }
 }
\ No newline at end of file
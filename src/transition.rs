@@ -0,0 +1,163 @@
+// Animated state transitions, started with StateTrans::Animate. While one plays, input is dropped
+// and states get no updates, so only the frame clock runs; commands still land every frame, and
+// transitions asked for meanwhile are applied once it finishes. A crossfaded or sliding Swap calls
+// the leaving state's exit, then the coming state's init, as the effect starts, so the leaving state
+// is drawn from whatever its exit left behind. A Push inits as it starts, a Pop exits once it ends,
+// and Fade applies the whole transition halfway through.
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Effect {
+  // fades out to the color, applies the transition halfway through, then fades back in;
+  // works with any transition
+  Fade([f32; 4]),
+  // draws both states at once, with an Opacity resource in each world going from 1 to 0 for the
+  // state leaving and 0 to 1 for the one coming in. Piston can't blend whole frames, so states
+  // that want to fade apply it to the colors they draw with.
+  Crossfade,
+  // the state coming in pushes the one leaving off screen, both moving this way
+  Slide(Direction)
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Direction {
+  Left,
+  Right,
+  Up,
+  Down
+}
+
+impl Direction {
+  fn unit(&self) -> [f64; 2] {
+    match *self {
+      Direction::Left => [-1.0, 0.0],
+      Direction::Right => [1.0, 0.0],
+      Direction::Up => [0.0, -1.0],
+      Direction::Down => [0.0, 1.0]
+    }
+  }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Easing {
+  Linear,
+  EaseIn,
+  EaseOut,
+  EaseInOut
+}
+
+impl Easing {
+  // maps progress from 0 to 1 onto the eased progress, also from 0 to 1
+  pub fn apply(&self, t: f64) -> f64 {
+    let t = t.max(0.0).min(1.0);
+    match *self {
+      Easing::Linear => t,
+      Easing::EaseIn => t * t,
+      Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+      Easing::EaseInOut => t * t * (3.0 - 2.0 * t)
+    }
+  }
+}
+
+// Only present in a state's world while a crossfade draws it
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Opacity(pub f32);
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Transition {
+  effect: Effect,
+  duration: f64,
+  easing: Easing
+}
+
+impl Transition {
+  // duration is in seconds
+  pub fn new(effect: Effect, duration: f64) -> Transition {
+    if duration <= 0.0 {
+      panic!("Error: Transition duration must be positive")
+    }
+    Transition {
+      effect: effect,
+      duration: duration,
+      easing: Easing::Linear
+    }
+  }
+
+  pub fn easing(mut self, easing: Easing) -> Transition {
+    self.easing = easing;
+    self
+  }
+
+  pub fn effect(&self) -> Effect {
+    self.effect
+  }
+
+  pub fn duration(&self) -> f64 {
+    self.duration
+  }
+
+  // for Fade: how much of the color covers the screen, which peaks halfway through
+  pub fn cover(&self, progress: f64) -> f64 {
+    if progress < 0.5 {
+      self.easing.apply(progress * 2.0)
+    } else {
+      1.0 - self.easing.apply((progress - 0.5) * 2.0)
+    }
+  }
+
+  // for Slide: how far the leaving and coming states are moved, given the screen size
+  pub fn offsets(&self, progress: f64, size: [f64; 2]) -> ([f64; 2], [f64; 2]) {
+    let eased = self.easing.apply(progress);
+    let unit = match self.effect {
+      Effect::Slide(direction) => direction.unit(),
+      _ => [0.0, 0.0]
+    };
+    let leaving = [unit[0] * eased * size[0], unit[1] * eased * size[1]];
+    let coming = [unit[0] * (eased - 1.0) * size[0], unit[1] * (eased - 1.0) * size[1]];
+    (leaving, coming)
+  }
+
+  // for Crossfade: the opacities of the leaving and coming states
+  pub fn opacities(&self, progress: f64) -> (f32, f32) {
+    let eased = self.easing.apply(progress) as f32;
+    (1.0 - eased, eased)
+  }
+}
+
+#[cfg(test)]
+mod transition_tests {
+  use super::*;
+
+  // describe: transition effects
+
+  // it should ease from 0 to 1 and clamp progress outside that range
+  #[test]
+  fn test_easing() {
+    for easing in &[Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut] {
+      assert_eq!(easing.apply(0.0), 0.0);
+      assert_eq!(easing.apply(1.0), 1.0);
+      assert_eq!(easing.apply(1.5), 1.0);
+    }
+    assert_eq!(Easing::EaseIn.apply(0.5), 0.25);
+    assert_eq!(Easing::EaseOut.apply(0.5), 0.75);
+    assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+  }
+
+  // it should cover the screen fully halfway through a fade
+  #[test]
+  fn test_cover() {
+    let fade = Transition::new(Effect::Fade([0.0, 0.0, 0.0, 1.0]), 1.0);
+    assert_eq!(fade.cover(0.0), 0.0);
+    assert_eq!(fade.cover(0.25), 0.5);
+    assert_eq!(fade.cover(0.5), 1.0);
+    assert_eq!(fade.cover(1.0), 0.0);
+  }
+
+  // it should slide the coming state in as the leaving one slides out
+  #[test]
+  fn test_offsets() {
+    let slide = Transition::new(Effect::Slide(Direction::Left), 0.5).easing(Easing::EaseInOut);
+    assert_eq!(slide.offsets(0.0, [640.0, 480.0]), ([0.0, 0.0], [640.0, 0.0]));
+    assert_eq!(slide.offsets(0.5, [640.0, 480.0]), ([-320.0, 0.0], [320.0, 0.0]));
+    assert_eq!(slide.offsets(1.0, [640.0, 480.0]), ([-640.0, 0.0], [0.0, 0.0]));
+  }
+}